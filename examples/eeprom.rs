@@ -1,7 +1,7 @@
 //! Access the EEPROM of FTDI devices.
 
-use rftdi::Ftdi;
-use std::{error, io, io::Write, process, str::FromStr, time::Duration};
+use rftdi::{eeprom::EepromBackup, Ftdi};
+use std::{error, fs::File, io, io::Write, path::PathBuf, process, str::FromStr, time::Duration};
 use structopt::StructOpt;
 
 struct UsbIds {
@@ -18,7 +18,7 @@ impl FromStr for UsbIds {
                 let (vid, pid) = (u16::from_str_radix(vid, 16)?, u16::from_str_radix(pid, 16)?);
                 Ok(Self { vid, pid })
             }
-            _ => Err(format!("USB ID format: `vid:pid`").into()),
+            _ => Err("USB ID format: `vid:pid`".into()),
         }
     }
 }
@@ -48,11 +48,19 @@ impl CommonOpts {
         if response.trim() == "y" {
             Ok(())
         } else {
-            Err(format!("operation canceled by user").into())
+            Err("operation canceled by user".into())
         }
     }
 
     fn open(&self) -> Result<Ftdi, Box<dyn error::Error>> {
+        // Allow running this example in CI, without any FTDI hardware.
+        #[cfg(feature = "sim")]
+        {
+            if let Ok(model) = std::env::var("RFTDI_SIM") {
+                return Ok(Ftdi::from_backend(rftdi::sim::SimDevice::new(&model)?)?);
+            }
+        }
+
         let ftdi = match &self.id {
             Some(ids) => Ftdi::open_by_id(ids.vid, ids.pid)?,
             None => Ftdi::open_unique()?,
@@ -88,6 +96,30 @@ enum Opts {
         raw: bool,
     },
 
+    /// Decodes and prints the device configuration stored in the EEPROM.
+    Decode {
+        #[structopt(flatten)]
+        common: CommonOpts,
+    },
+
+    /// Saves a backup of the EEPROM contents to a file.
+    Backup {
+        #[structopt(flatten)]
+        common: CommonOpts,
+
+        /// The file to write the backup to.
+        file: PathBuf,
+    },
+
+    /// Restores an EEPROM backup created with `backup`.
+    Restore {
+        #[structopt(flatten)]
+        common: CommonOpts,
+
+        /// The backup file to restore.
+        file: PathBuf,
+    },
+
     /// Writes data to the EEPROM.
     Write {
         #[structopt(flatten)]
@@ -141,17 +173,17 @@ fn run() -> Result<(), Box<dyn error::Error>> {
         } => {
             let ftdi = common.open()?;
 
+            let mut words = vec![0; usize::from(count)];
+            ftdi.read_eeprom_words(addr, &mut words)?;
+
             if raw {
                 let stdout = io::stdout();
                 let mut stdout = stdout.lock();
-                for a in addr..addr + count {
-                    let word = ftdi.read_eeprom_word(a)?;
+                for word in words {
                     stdout.write_all(&word.to_le_bytes())?;
                 }
             } else {
-                for (i, a) in (addr..addr + count).enumerate() {
-                    let word = ftdi.read_eeprom_word(a)?;
-
+                for (i, (a, word)) in (addr..).zip(words).enumerate() {
                     if i % 16 == 0 {
                         print!("{:04x}:", a);
                     }
@@ -164,6 +196,37 @@ fn run() -> Result<(), Box<dyn error::Error>> {
                 }
             }
         }
+        Opts::Decode { common } => {
+            let ftdi = common.open()?;
+
+            println!("{:#?}", ftdi.read_eeprom_config()?);
+            if let Err(e) = ftdi.verify_eeprom() {
+                eprintln!("WARNING: {}", e);
+            }
+        }
+        Opts::Backup { common, file } => {
+            let ftdi = common.open()?;
+
+            let backup = ftdi.backup_eeprom()?;
+            backup.write_to(File::create(&file)?)?;
+            eprintln!(
+                "Saved {} words to {}.",
+                backup.words().len(),
+                file.display()
+            );
+        }
+        Opts::Restore { common, file } => {
+            let ftdi = common.open()?;
+
+            let backup = EepromBackup::read_from(File::open(&file)?)?;
+            common.confirm(&format!(
+                "overwrite the EEPROM with the backup of device {}",
+                backup.serial().unwrap_or("<unknown>")
+            ))?;
+
+            ftdi.restore_eeprom(&backup)?;
+            eprintln!("EEPROM restored.");
+        }
         Opts::Write {
             common,
             addr,
@@ -181,9 +244,7 @@ fn run() -> Result<(), Box<dyn error::Error>> {
 
             common.confirm("write to the EEPROM")?;
 
-            for a in addr..addr + count {
-                ftdi.write_eeprom_word(a, word)?;
-            }
+            ftdi.write_eeprom_words(addr, &vec![word; usize::from(count)])?;
         }
     }
 
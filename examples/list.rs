@@ -16,11 +16,21 @@ fn main() {
 fn run() -> Result<()> {
     env_logger::init();
 
-    for device in rftdi::devices()? {
-        match device.and_then(|device| dump_device(&device)) {
+    // Allow running this example in CI, without any FTDI hardware.
+    #[cfg(feature = "sim")]
+    {
+        if let Ok(model) = std::env::var("RFTDI_SIM") {
+            let device = Ftdi::from_backend(rftdi::sim::SimDevice::new(&model)?)?;
+            return dump_device(&device);
+        }
+    }
+
+    for info in rftdi::list_devices()? {
+        println!("{}", info);
+        match info.open().and_then(|device| dump_ports(&device)) {
             Ok(()) => {}
             Err(err) => {
-                eprintln!("error: {}", err);
+                eprintln!("  error: {}", err);
             }
         }
     }
@@ -28,6 +38,7 @@ fn run() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "sim")]
 fn dump_device(device: &Ftdi) -> Result<()> {
     println!(
         "Bus {:03} Address {:03}: ID {:04x}:{:04x} {} ({:?})",
@@ -39,6 +50,10 @@ fn dump_device(device: &Ftdi) -> Result<()> {
         device.product()?,
     );
 
+    dump_ports(device)
+}
+
+fn dump_ports(device: &Ftdi) -> Result<()> {
     for port_num in 0..device.num_ports() {
         print!("  Port {}:", port_num);
         let mut port = device.open_port(port_num)?;
@@ -0,0 +1,463 @@
+//! Asynchronous port I/O.
+//!
+//! This module is only available when the `async` Cargo feature is enabled. The `tokio` feature
+//! additionally implements tokio's I/O traits for [`AsyncPort`].
+//!
+//! [`AsyncPort`]: struct.AsyncPort.html
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::{fmt, mem};
+
+use crate::backend::{BulkInCallback, BulkInStream};
+use crate::port::RxState;
+use crate::{bitmode, Error, ErrorKind, FlowControl, ModemStatus, Port, Result, UsbHandle};
+
+/// Number of bulk IN transfers to keep queued.
+const RX_QUEUE_DEPTH: usize = 8;
+
+/// A serial port performing I/O asynchronously.
+///
+/// An `AsyncPort` continuously receives data from the device in the background, keeping several
+/// bulk IN transfers queued, so that no data is lost between polls, even at high baud rates.
+/// Writes and control requests are performed by a dedicated worker thread.
+///
+/// `AsyncPort` implements the `AsyncRead` and `AsyncWrite` traits from `futures-io`, and those
+/// from `tokio` if the `tokio` feature is enabled. It is runtime-agnostic and can be used with
+/// any executor.
+///
+/// Writes are buffered: `poll_write` hands the data to the worker thread and returns immediately.
+/// Errors are reported by the next write or flush. Like the blocking `flush`, flushing waits until
+/// the device has transmitted all data in its TX FIFO.
+pub struct AsyncPort {
+    index: u8,
+    rx: Arc<Mutex<RxShared>>,
+    stream: Option<Box<dyn BulkInStream>>,
+    jobs: Option<mpsc::Sender<Job>>,
+    worker: Option<JoinHandle<()>>,
+    /// The write that is currently being performed by the worker thread.
+    write: Option<JobFuture<()>>,
+    /// The flush that is currently being performed by the worker thread.
+    flush: Option<JobFuture<()>>,
+}
+
+/// Receive state shared with the bulk IN stream.
+struct RxShared {
+    rx: RxState,
+    waker: Option<Waker>,
+    /// Error that stopped the stream (taken once it is reported).
+    error: Option<Error>,
+    stopped: bool,
+}
+
+type Job = Box<dyn FnOnce(&mut Port) + Send>;
+
+impl Port<bitmode::Serial> {
+    /// Converts this port into an `AsyncPort`.
+    ///
+    /// Any data that was already received by this port will be returned by the `AsyncPort`.
+    pub fn into_async(mut self) -> Result<AsyncPort> {
+        let rx = Arc::new(Mutex::new(RxShared {
+            rx: mem::take(&mut self.rx),
+            waker: None,
+            error: None,
+            stopped: false,
+        }));
+
+        let stream = start_stream(&self, rx.clone())?;
+
+        let index = self.index();
+        let (jobs, recv) = mpsc::channel::<Job>();
+        let worker = thread::Builder::new()
+            .name(format!("rftdi port {}", index))
+            .spawn(move || {
+                let mut port = self;
+                for job in recv {
+                    job(&mut port);
+                }
+            })
+            .map_err(|e| Error::new(ErrorKind::Other, e))?;
+
+        Ok(AsyncPort {
+            index,
+            rx,
+            stream: Some(stream),
+            jobs: Some(jobs),
+            worker: Some(worker),
+            write: None,
+            flush: None,
+        })
+    }
+}
+
+/// Starts continuously receiving data for `port`, storing it in `rx`.
+fn start_stream(port: &Port, rx: Arc<Mutex<RxShared>>) -> Result<Box<dyn BulkInStream>> {
+    let packet_size = usize::from(port.max_packet_size());
+    let make_callback = |rx: Arc<Mutex<RxShared>>| -> BulkInCallback {
+        Box::new(move |result| {
+            let mut rx = rx.lock().unwrap();
+            match result {
+                Ok(data) => {
+                    rx.rx.ingest(data, packet_size);
+                    if rx.rx.pending.is_empty() {
+                        // Status-only packet, no need to wake the reader.
+                        return;
+                    }
+                }
+                Err(e) => {
+                    rx.error = Some(e);
+                    rx.stopped = true;
+                }
+            }
+
+            if let Some(waker) = rx.waker.take() {
+                waker.wake();
+            }
+        })
+    };
+
+    let usb = port.usb_handle();
+    let stream = usb.start_bulk_in_stream(
+        port.ep_in(),
+        port.rx_transfer_size(),
+        RX_QUEUE_DEPTH,
+        make_callback(rx.clone()),
+    )?;
+
+    match stream {
+        Some(stream) => Ok(stream),
+        None => Ok(Box::new(ThreadStream::start(
+            usb,
+            port.ep_in(),
+            port.rx_transfer_size(),
+            make_callback(rx),
+        )?)),
+    }
+}
+
+/// Fallback bulk IN stream for backends without asynchronous transfers.
+///
+/// Calls `read_bulk` in a loop on a background thread.
+struct ThreadStream {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ThreadStream {
+    /// Timeout of each `read_bulk` call, which bounds the time it takes to stop the thread.
+    const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn start(
+        usb: UsbHandle,
+        endpoint: u8,
+        transfer_size: usize,
+        mut callback: BulkInCallback,
+    ) -> Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop2 = stop.clone();
+        let thread = thread::Builder::new()
+            .name(String::from("rftdi bulk IN"))
+            .spawn(move || {
+                let mut buf = vec![0; transfer_size];
+                while !stop2.load(Ordering::Relaxed) {
+                    match usb.read_bulk(endpoint, &mut buf, Self::POLL_TIMEOUT) {
+                        Ok(n) => callback(Ok(&buf[..n])),
+                        Err(e) if matches!(e.kind(), ErrorKind::Timeout) => {}
+                        Err(e) => {
+                            callback(Err(e));
+                            break;
+                        }
+                    }
+                }
+            })
+            .map_err(|e| Error::new(ErrorKind::Other, e))?;
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl BulkInStream for ThreadStream {}
+
+impl Drop for ThreadStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Future resolving to the result of a job run on the worker thread.
+struct JobFuture<T> {
+    slot: Arc<Mutex<JobSlot<T>>>,
+}
+
+struct JobSlot<T> {
+    result: Option<Result<T>>,
+    waker: Option<Waker>,
+}
+
+impl<T> Future for JobFuture<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T>> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl AsyncPort {
+    /// Runs `f` on the worker thread, returning a future that resolves to its result.
+    fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Port) -> Result<T> + Send + 'static,
+    ) -> JobFuture<T> {
+        let slot = Arc::new(Mutex::new(JobSlot {
+            result: None,
+            waker: None,
+        }));
+
+        let job_slot = slot.clone();
+        let job: Job = Box::new(move |port| {
+            let result = f(port);
+            let mut slot = job_slot.lock().unwrap();
+            slot.result = Some(result);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        });
+
+        let sent = self
+            .jobs
+            .as_ref()
+            .is_some_and(|jobs| jobs.send(job).is_ok());
+        if !sent {
+            slot.lock().unwrap().result = Some(Err(Error::other("port worker thread died")));
+        }
+
+        JobFuture { slot }
+    }
+
+    fn rx(&self) -> MutexGuard<'_, RxShared> {
+        self.rx.lock().unwrap()
+    }
+
+    /// Returns this port's 0-based index.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Returns the modem status received along with the most recent data packet.
+    ///
+    /// This does not perform any USB transfer.
+    pub fn last_modem_status(&self) -> ModemStatus {
+        self.rx().rx.modem_status
+    }
+
+    /// Returns and clears the line errors (OE, PE, FE and BI) received since the last call.
+    pub fn take_line_errors(&mut self) -> ModemStatus {
+        self.rx().rx.take_line_errors()
+    }
+
+    /// Polls the current modem status from the device.
+    pub async fn poll_modem_status(&self) -> Result<ModemStatus> {
+        self.run(|port| port.poll_modem_status()).await
+    }
+
+    /// Sets or clears the Data Terminal Ready (DTR) bit.
+    ///
+    /// Note that the DTR output pin is inverted (DTR#), so the pin state will be the opposite of
+    /// `dtr`.
+    pub async fn set_dtr(&self, dtr: bool) -> Result<()> {
+        self.run(move |port| port.set_dtr(dtr)).await
+    }
+
+    /// Sets or clears the Request To Send (RTS) bit.
+    ///
+    /// Note that the RTS output pin is inverted (RTS#), so the pin state will be the opposite of
+    /// `rts`.
+    pub async fn set_rts(&self, rts: bool) -> Result<()> {
+        self.run(move |port| port.set_rts(rts)).await
+    }
+
+    pub async fn set_flow_control(&self, flow: FlowControl) -> Result<()> {
+        self.run(move |port| port.set_flow_control(flow)).await
+    }
+
+    /// Polls the current status of the lower 8 I/O pins.
+    pub async fn read_pins(&self) -> Result<u8> {
+        self.run(|port| port.read_pins()).await
+    }
+
+    fn poll_read_inner(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let mut rx = self.rx();
+        if !rx.rx.pending.is_empty() {
+            return Poll::Ready(Ok(rx.rx.take(buf)));
+        }
+
+        if let Some(e) = rx.error.take() {
+            return Poll::Ready(Err(e.into()));
+        }
+        if rx.stopped {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "receiving data has stopped due to a previous error",
+            )));
+        }
+
+        rx.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Waits for the write in progress (if any) to finish.
+    fn poll_write_done(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(write) = &mut self.write {
+            let result = match Pin::new(write).poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            };
+            self.write = None;
+            result?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Waits for the write in progress to finish, and for the device to transmit all data.
+    fn poll_flush_inner(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.poll_write_done(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        if self.flush.is_none() {
+            self.flush = Some(self.run(|port| port.wait_tx_empty()));
+        }
+        let flush = self.flush.as_mut().unwrap();
+        let result = match Pin::new(flush).poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.flush = None;
+        Poll::Ready(result.map_err(Into::into))
+    }
+
+    fn poll_write_inner(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.poll_write_done(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        let data = buf.to_vec();
+        self.write = Some(self.run(move |port| port.write_all(&data)));
+        Poll::Ready(Ok(buf.len()))
+    }
+}
+
+impl Drop for AsyncPort {
+    fn drop(&mut self) {
+        // Stop receiving before the interface is released.
+        self.stream = None;
+
+        // Stop the worker thread after it has finished all pending jobs. This drops the `Port`,
+        // which releases the interface.
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            worker.join().ok();
+        }
+    }
+}
+
+impl fmt::Debug for AsyncPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncPort")
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl futures_io::AsyncRead for AsyncPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_read_inner(cx, buf)
+    }
+}
+
+impl futures_io::AsyncWrite for AsyncPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_write_inner(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flush_inner(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flush_inner(cx)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for AsyncPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.poll_read_inner(cx, buf.initialize_unfilled()) {
+            Poll::Ready(Ok(n)) => {
+                buf.advance(n);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for AsyncPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_write_inner(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flush_inner(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flush_inner(cx)
+    }
+}
@@ -0,0 +1,168 @@
+//! USB backend abstraction.
+//!
+//! `rftdi` does not talk to the operating system's USB stack directly. Instead, all USB traffic
+//! goes through an implementation of the [`Backend`] trait. By default, a libusb-based backend is
+//! used (enabled by the `rusb` Cargo feature), but other transports can be plugged in by
+//! implementing [`Backend`] and passing it to [`Ftdi::from_backend`].
+//!
+//! [`Backend`]: trait.Backend.html
+//! [`Ftdi::from_backend`]: ../struct.Ftdi.html#method.from_backend
+
+#[cfg(feature = "rusb")]
+pub(crate) mod libusb;
+
+use std::time::Duration;
+
+use crate::Result;
+
+/// The parts of a USB device descriptor used by `rftdi`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeviceDescriptor {
+    /// USB Vendor ID (`idVendor`).
+    pub vendor_id: u16,
+    /// USB Product ID (`idProduct`).
+    pub product_id: u16,
+    /// Device release number in binary-coded decimal (`bcdDevice`).
+    pub device_version: u16,
+    /// Number of configurations the device supports (`bNumConfigurations`).
+    pub num_configurations: u8,
+    /// String descriptor index of the manufacturer string (`iManufacturer`).
+    pub manufacturer_string_index: Option<u8>,
+    /// String descriptor index of the product string (`iProduct`).
+    pub product_string_index: Option<u8>,
+    /// String descriptor index of the serial number string (`iSerialNumber`).
+    pub serial_number_string_index: Option<u8>,
+}
+
+/// Description of an interface in the device's active configuration.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InterfaceDescriptor {
+    /// The interface number (`bInterfaceNumber`).
+    pub number: u8,
+    /// The number of alternate settings of the interface.
+    pub num_alt_settings: u8,
+    /// The endpoints of the interface's default alternate setting.
+    pub endpoints: Vec<EndpointDescriptor>,
+}
+
+/// Description of a USB endpoint.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct EndpointDescriptor {
+    /// The endpoint address (`bEndpointAddress`), including the direction bit.
+    pub address: u8,
+    /// Maximum packet size of the endpoint (`wMaxPacketSize`).
+    pub max_packet_size: u16,
+}
+
+impl EndpointDescriptor {
+    /// Returns whether this is an IN (device-to-host) endpoint.
+    pub fn is_in(&self) -> bool {
+        self.address & 0x80 != 0
+    }
+}
+
+/// Callback invoked with the result of every completed transfer of a [`BulkInStream`].
+///
+/// [`BulkInStream`]: trait.BulkInStream.html
+pub type BulkInCallback = Box<dyn FnMut(Result<&[u8]>) + Send>;
+
+/// A running stream of bulk IN transfers, started by [`Backend::start_bulk_in_stream`].
+///
+/// Dropping the stream cancels all queued transfers and waits for them to finish.
+///
+/// [`Backend::start_bulk_in_stream`]: trait.Backend.html#method.start_bulk_in_stream
+pub trait BulkInStream: Send {}
+
+/// A USB transport that can drive an opened FTDI device.
+///
+/// Backends are shared by all ports of a device, which may be used from different threads at the
+/// same time. All methods therefore take `&self`, and implementations should avoid holding locks
+/// across transfers, so that ports don't block each other.
+///
+/// Implementations should report timeouts as [`ErrorKind::Timeout`], operations on a device that
+/// is no longer connected as [`ErrorKind::Disconnected`], and all other transport errors as
+/// [`ErrorKind::Usb`].
+///
+/// [`ErrorKind::Timeout`]: ../enum.ErrorKind.html#variant.Timeout
+/// [`ErrorKind::Disconnected`]: ../enum.ErrorKind.html#variant.Disconnected
+/// [`ErrorKind::Usb`]: ../enum.ErrorKind.html#variant.Usb
+pub trait Backend: Send + Sync {
+    /// Returns the device descriptor.
+    fn device_descriptor(&self) -> DeviceDescriptor;
+
+    /// Returns the interfaces of the device's active configuration.
+    fn interfaces(&self) -> Result<Vec<InterfaceDescriptor>>;
+
+    /// Returns the number of the bus the device is attached to.
+    fn bus_number(&self) -> u8;
+
+    /// Returns the address of the device on its bus.
+    fn address(&self) -> u8;
+
+    /// Reads the string descriptor at `index` and converts it to ASCII.
+    fn read_string(&self, index: u8, timeout: Duration) -> Result<String>;
+
+    /// Performs an IN control transfer, returning the number of Bytes read into `buf`.
+    fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize>;
+
+    /// Performs an OUT control transfer, returning the number of Bytes written from `buf`.
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize>;
+
+    /// Reads from a bulk IN endpoint, returning the number of Bytes read into `buf`.
+    ///
+    /// If the transfer times out after some data was received, this should return the received
+    /// data instead of an error.
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize>;
+
+    /// Writes to a bulk OUT endpoint, returning the number of Bytes written from `buf`.
+    ///
+    /// If the transfer times out after some data was sent, this should return the number of Bytes
+    /// sent instead of an error.
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> Result<usize>;
+
+    /// Claims an interface for exclusive use.
+    fn claim_interface(&self, interface: u8) -> Result<()>;
+
+    /// Releases a previously claimed interface.
+    fn release_interface(&self, interface: u8) -> Result<()>;
+
+    /// Performs a USB port reset of the device.
+    fn reset(&self) -> Result<()>;
+
+    /// Starts continuously reading from a bulk IN endpoint.
+    ///
+    /// The backend should keep `queue_depth` transfers of `transfer_size` Bytes queued at all
+    /// times, so that no data is lost while a completed transfer is being processed. `callback`
+    /// must be invoked with the data of every completed transfer, in order. If a transfer fails,
+    /// `callback` is invoked with the error and the stream stops.
+    ///
+    /// Backends without support for asynchronous transfers can return `Ok(None)` (which the
+    /// default implementation does). `rftdi` will then call `read_bulk` from a background thread
+    /// instead.
+    fn start_bulk_in_stream(
+        &self,
+        endpoint: u8,
+        transfer_size: usize,
+        queue_depth: usize,
+        callback: BulkInCallback,
+    ) -> Result<Option<Box<dyn BulkInStream>>> {
+        let _ = (endpoint, transfer_size, queue_depth, callback);
+        Ok(None)
+    }
+}
@@ -0,0 +1,410 @@
+//! The default backend, using libusb via `rusb`.
+
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::{ptr, slice};
+
+use libusb1_sys::constants::*;
+use libusb1_sys::libusb_transfer;
+use rusb::UsbContext;
+
+use super::{
+    Backend, BulkInCallback, BulkInStream, DeviceDescriptor, EndpointDescriptor,
+    InterfaceDescriptor,
+};
+use crate::{Error, ErrorKind, Result};
+
+/// A device opened via libusb.
+pub(crate) struct LibusbBackend {
+    handle: rusb::DeviceHandle<rusb::GlobalContext>,
+    descr: DeviceDescriptor,
+}
+
+impl LibusbBackend {
+    pub(crate) fn open(device: &rusb::Device<rusb::GlobalContext>) -> Result<Self> {
+        let descr = device.device_descriptor().map_err(Error::usb)?;
+
+        let mut handle = device.open().map_err(|e| {
+            if cfg!(windows) && matches!(e, rusb::Error::NotSupported | rusb::Error::NotFound) {
+                // Provide a more helpful error message on non-plug-and-play platforms.
+                Error::new(
+                    ErrorKind::Usb,
+                    format!(
+                        "{} (this error may be caused by not having the WinUSB driver installed; \
+                            use Zadig (https://zadig.akeo.ie/) to install it for the FTDI device; \
+                            this will replace any existing driver)",
+                        e
+                    ),
+                )
+            } else {
+                Error::usb(e)
+            }
+        })?;
+
+        handle.set_auto_detach_kernel_driver(true).ok();
+
+        Ok(Self {
+            handle,
+            descr: DeviceDescriptor {
+                vendor_id: descr.vendor_id(),
+                product_id: descr.product_id(),
+                device_version: bcd(descr.device_version()),
+                num_configurations: descr.num_configurations(),
+                manufacturer_string_index: descr.manufacturer_string_index(),
+                product_string_index: descr.product_string_index(),
+                serial_number_string_index: descr.serial_number_string_index(),
+            },
+        })
+    }
+}
+
+/// Converts a libusb return code to a `Result`.
+///
+/// `rusb` requires `&mut` access for some operations that libusb allows on a shared handle. We
+/// call those directly, so that interfaces can be claimed while another thread performs transfers.
+fn check(ret: c_int) -> Result<()> {
+    let err = match ret {
+        0 => return Ok(()),
+        LIBUSB_ERROR_IO => rusb::Error::Io,
+        LIBUSB_ERROR_INVALID_PARAM => rusb::Error::InvalidParam,
+        LIBUSB_ERROR_ACCESS => rusb::Error::Access,
+        LIBUSB_ERROR_NO_DEVICE => rusb::Error::NoDevice,
+        LIBUSB_ERROR_NOT_FOUND => rusb::Error::NotFound,
+        LIBUSB_ERROR_BUSY => rusb::Error::Busy,
+        LIBUSB_ERROR_TIMEOUT => rusb::Error::Timeout,
+        LIBUSB_ERROR_OVERFLOW => rusb::Error::Overflow,
+        LIBUSB_ERROR_PIPE => rusb::Error::Pipe,
+        LIBUSB_ERROR_INTERRUPTED => rusb::Error::Interrupted,
+        LIBUSB_ERROR_NO_MEM => rusb::Error::NoMem,
+        LIBUSB_ERROR_NOT_SUPPORTED => rusb::Error::NotSupported,
+        _ => rusb::Error::Other,
+    };
+    Err(Error::usb(err))
+}
+
+/// Converts a `rusb::Version` back to its binary-coded decimal representation.
+pub(crate) fn bcd(version: rusb::Version) -> u16 {
+    let major = u16::from(version.major());
+    (major / 10) << 12
+        | (major % 10) << 8
+        | u16::from(version.minor()) << 4
+        | u16::from(version.sub_minor())
+}
+
+impl Backend for LibusbBackend {
+    fn device_descriptor(&self) -> DeviceDescriptor {
+        self.descr.clone()
+    }
+
+    fn interfaces(&self) -> Result<Vec<InterfaceDescriptor>> {
+        let conf_descr = self
+            .handle
+            .device()
+            .active_config_descriptor()
+            .map_err(Error::usb)?;
+
+        let interfaces = conf_descr
+            .interfaces()
+            .map(|intf| {
+                let mut num_alt_settings = 0;
+                let mut endpoints = Vec::new();
+                for (i, descr) in intf.descriptors().enumerate() {
+                    num_alt_settings += 1;
+                    if i == 0 {
+                        endpoints = descr
+                            .endpoint_descriptors()
+                            .map(|ep| EndpointDescriptor {
+                                address: ep.address(),
+                                max_packet_size: ep.max_packet_size(),
+                            })
+                            .collect();
+                    }
+                }
+
+                InterfaceDescriptor {
+                    number: intf.number(),
+                    num_alt_settings,
+                    endpoints,
+                }
+            })
+            .collect();
+        Ok(interfaces)
+    }
+
+    fn bus_number(&self) -> u8 {
+        self.handle.device().bus_number()
+    }
+
+    fn address(&self) -> u8 {
+        self.handle.device().address()
+    }
+
+    fn read_string(&self, index: u8, _timeout: Duration) -> Result<String> {
+        self.handle
+            .read_string_descriptor_ascii(index)
+            .map_err(Error::usb)
+    }
+
+    fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.handle
+            .read_control(request_type, request, value, index, buf, timeout)
+            .map_err(Error::usb)
+    }
+
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.handle
+            .write_control(request_type, request, value, index, buf, timeout)
+            .map_err(Error::usb)
+    }
+
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.handle
+            .read_bulk(endpoint, buf, timeout)
+            .map_err(Error::usb)
+    }
+
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> Result<usize> {
+        self.handle
+            .write_bulk(endpoint, buf, timeout)
+            .map_err(Error::usb)
+    }
+
+    fn claim_interface(&self, interface: u8) -> Result<()> {
+        // Safety: `as_raw` returns a valid handle, and libusb allows concurrent use of it.
+        check(unsafe {
+            libusb1_sys::libusb_claim_interface(self.handle.as_raw(), c_int::from(interface))
+        })
+    }
+
+    fn release_interface(&self, interface: u8) -> Result<()> {
+        // Safety: see `claim_interface`.
+        check(unsafe {
+            libusb1_sys::libusb_release_interface(self.handle.as_raw(), c_int::from(interface))
+        })
+    }
+
+    fn reset(&self) -> Result<()> {
+        // Safety: see `claim_interface`.
+        check(unsafe { libusb1_sys::libusb_reset_device(self.handle.as_raw()) })
+    }
+
+    fn start_bulk_in_stream(
+        &self,
+        endpoint: u8,
+        transfer_size: usize,
+        queue_depth: usize,
+        callback: BulkInCallback,
+    ) -> Result<Option<Box<dyn BulkInStream>>> {
+        let stream =
+            LibusbStream::start(&self.handle, endpoint, transfer_size, queue_depth, callback)?;
+        Ok(Some(Box::new(stream)))
+    }
+}
+
+/// State shared between a `LibusbStream`, its transfer callbacks and its event thread.
+struct StreamShared {
+    callback: Mutex<BulkInCallback>,
+    /// Number of transfers that are currently submitted.
+    active: AtomicUsize,
+    stopping: AtomicBool,
+}
+
+/// A transfer allocated by libusb, and its data buffer.
+struct StreamTransfer {
+    transfer: *mut libusb_transfer,
+    _buf: Box<[u8]>,
+}
+
+/// A stream of bulk IN transfers using libusb's asynchronous API.
+struct LibusbStream {
+    shared: Arc<StreamShared>,
+    transfers: Vec<StreamTransfer>,
+    events: Option<JoinHandle<()>>,
+}
+
+// Safety: The raw transfer pointers are only used to cancel and free the transfers, which libusb
+// allows from any thread.
+unsafe impl Send for LibusbStream {}
+
+impl BulkInStream for LibusbStream {}
+
+impl LibusbStream {
+    fn start(
+        handle: &rusb::DeviceHandle<rusb::GlobalContext>,
+        endpoint: u8,
+        transfer_size: usize,
+        queue_depth: usize,
+        callback: BulkInCallback,
+    ) -> Result<Self> {
+        let shared = Arc::new(StreamShared {
+            callback: Mutex::new(callback),
+            active: AtomicUsize::new(0),
+            stopping: AtomicBool::new(false),
+        });
+
+        let mut this = Self {
+            shared: shared.clone(),
+            transfers: Vec::with_capacity(queue_depth),
+            events: None,
+        };
+
+        for _ in 0..queue_depth {
+            // Safety: allocating a transfer has no preconditions.
+            let transfer = unsafe { libusb1_sys::libusb_alloc_transfer(0) };
+            if transfer.is_null() {
+                return Err(Error::usb(rusb::Error::NoMem));
+            }
+
+            let mut buf = vec![0; transfer_size].into_boxed_slice();
+            // Safety: `transfer` is valid, and both `buf` and `shared` outlive it (`Drop` waits for
+            // all transfers to finish before they are freed).
+            unsafe {
+                libusb1_sys::libusb_fill_bulk_transfer(
+                    transfer,
+                    handle.as_raw(),
+                    endpoint,
+                    buf.as_mut_ptr(),
+                    transfer_size as c_int,
+                    stream_callback,
+                    Arc::as_ptr(&shared) as *mut c_void,
+                    0,
+                );
+            }
+            this.transfers.push(StreamTransfer {
+                transfer,
+                _buf: buf,
+            });
+        }
+
+        for transfer in &this.transfers {
+            shared.active.fetch_add(1, Ordering::SeqCst);
+            // Safety: the transfer was filled in above.
+            if let Err(e) = check(unsafe { libusb1_sys::libusb_submit_transfer(transfer.transfer) })
+            {
+                shared.active.fetch_sub(1, Ordering::SeqCst);
+                // `Drop` cancels the transfers we already submitted.
+                return Err(e);
+            }
+        }
+
+        // Transfer callbacks are invoked by whichever thread handles libusb events, so make sure
+        // there is one.
+        this.events = Some(thread::spawn(move || {
+            let context = rusb::GlobalContext::default().as_raw();
+            while shared.active.load(Ordering::SeqCst) != 0 {
+                // Safety: the global context lives forever.
+                unsafe {
+                    libusb1_sys::libusb_handle_events_completed(context, ptr::null_mut());
+                }
+            }
+        }));
+
+        Ok(this)
+    }
+}
+
+impl Drop for LibusbStream {
+    fn drop(&mut self) {
+        self.shared.stopping.store(true, Ordering::SeqCst);
+
+        // A callback might resubmit its transfer right after we cancelled it, so keep cancelling
+        // until all transfers are done.
+        while self.shared.active.load(Ordering::SeqCst) != 0 {
+            for transfer in &self.transfers {
+                // Safety: the transfer is still allocated. Cancelling a transfer that isn't
+                // submitted just returns an error.
+                unsafe {
+                    libusb1_sys::libusb_cancel_transfer(transfer.transfer);
+                }
+            }
+
+            if self.events.is_none() {
+                // Setup failed before starting the event thread, handle events ourselves.
+                let context = rusb::GlobalContext::default().as_raw();
+                // Safety: the global context lives forever.
+                unsafe {
+                    libusb1_sys::libusb_handle_events_completed(context, ptr::null_mut());
+                }
+            } else {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        if let Some(events) = self.events.take() {
+            events.join().ok();
+        }
+
+        for transfer in &self.transfers {
+            // Safety: the transfer is no longer submitted.
+            unsafe {
+                libusb1_sys::libusb_free_transfer(transfer.transfer);
+            }
+        }
+    }
+}
+
+extern "system" fn stream_callback(transfer: *mut libusb_transfer) {
+    // Safety: libusb passes the transfer we submitted, whose `user_data` points to the
+    // `StreamShared`, which outlives the transfer.
+    let (shared, status) = unsafe {
+        (
+            &*((*transfer).user_data as *const StreamShared),
+            (*transfer).status,
+        )
+    };
+
+    let resubmit = match status {
+        LIBUSB_TRANSFER_COMPLETED => {
+            // Safety: libusb has written `actual_length` Bytes to the buffer.
+            let data = unsafe {
+                slice::from_raw_parts((*transfer).buffer, (*transfer).actual_length as usize)
+            };
+            (shared.callback.lock().unwrap())(Ok(data));
+            true
+        }
+        LIBUSB_TRANSFER_CANCELLED => false,
+        status => {
+            let err = match status {
+                LIBUSB_TRANSFER_NO_DEVICE => rusb::Error::NoDevice,
+                LIBUSB_TRANSFER_TIMED_OUT => rusb::Error::Timeout,
+                LIBUSB_TRANSFER_STALL => rusb::Error::Pipe,
+                LIBUSB_TRANSFER_OVERFLOW => rusb::Error::Overflow,
+                _ => rusb::Error::Io,
+            };
+            // Only report the first error, all other transfers will fail as well.
+            if !shared.stopping.swap(true, Ordering::SeqCst) {
+                (shared.callback.lock().unwrap())(Err(Error::usb(err)));
+            }
+            false
+        }
+    };
+
+    // Safety: the transfer is no longer submitted, so it can be resubmitted.
+    if resubmit
+        && !shared.stopping.load(Ordering::SeqCst)
+        && unsafe { libusb1_sys::libusb_submit_transfer(transfer) } == 0
+    {
+        return;
+    }
+
+    shared.active.fetch_sub(1, Ordering::SeqCst);
+}
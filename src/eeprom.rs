@@ -0,0 +1,839 @@
+//! EEPROM configuration.
+//!
+//! FTDI devices load their USB descriptors and chip configuration from an EEPROM. The layout of
+//! that EEPROM differs between chip families. [`EepromConfig`] decodes the EEPROM image of all
+//! supported chips into their settings, and encodes modified settings back into an image.
+//!
+//! [`EepromConfig`]: struct.EepromConfig.html
+
+mod cbus;
+
+pub(crate) use cbus::io_mode_pins;
+pub use cbus::{CbusFunction, Ft232hCbus, Ft232rCbus, FtxCbus};
+
+use std::io::{self, BufRead};
+use std::{cmp, fmt};
+
+use crate::prop::{self, ChipType, DeviceProps};
+use crate::{Error, ErrorKind, Result};
+
+// Byte addresses of common fields.
+const ADDR_VID: usize = 0x02;
+const ADDR_PID: usize = 0x04;
+const ADDR_ATTRIBUTES: usize = 0x08;
+const ADDR_MAX_POWER: usize = 0x09;
+const ADDR_CHIP_CONFIG: usize = 0x0A;
+/// Pointer and length of the manufacturer, product and serial number strings.
+const ADDR_MANUFACTURER: usize = 0x0E;
+const ADDR_PRODUCT: usize = 0x10;
+const ADDR_SERIAL: usize = 0x12;
+
+/// Configuration descriptor `bmAttributes` bits.
+const ATTR_RESERVED: u8 = 0x80;
+const ATTR_SELF_POWERED: u8 = 0x40;
+const ATTR_REMOTE_WAKEUP: u8 = 0x20;
+
+/// Chip configuration bit enabling the serial number string.
+const CONFIG_USE_SERIAL: u8 = 0x08;
+
+/// USB string descriptor type.
+const DESCRIPTOR_STRING: u8 = 0x03;
+
+/// Maximum current a USB device may request, in mA.
+const MAX_CURRENT_LIMIT: u16 = 500;
+
+/// Initial value of the checksum.
+const CHECKSUM_SEED: u16 = 0xAAAA;
+
+/// Word range of the FT-X user area, which is not covered by the checksum.
+const X_USER_AREA: std::ops::Range<usize> = 0x12..0x40;
+/// Word address of the FT-X checksum, which is independent of the MTP size.
+const X_CHECKSUM_ADDR: usize = 0x7F;
+
+/// Location of chip-specific settings in the EEPROM.
+struct Layout {
+    /// Byte offset of the first string descriptor (with bit 7 set, like the string pointers).
+    strings: u8,
+    /// Byte address and mask of the VCP driver bit of each port.
+    drivers: &'static [(usize, u8)],
+    /// Byte address and mask of the high current bit of each port, if supported.
+    high_current: &'static [(usize, u8)],
+    /// Byte address and bit offset of the settings nibble of each pin group.
+    pin_groups: &'static [(usize, u8)],
+}
+
+fn layout(chip: ChipType) -> &'static Layout {
+    match chip {
+        ChipType::Am | ChipType::Bm => &Layout {
+            strings: 0x94,
+            drivers: &[],
+            high_current: &[],
+            pin_groups: &[],
+        },
+        ChipType::FT2232C => &Layout {
+            strings: 0x96,
+            drivers: &[(0x00, 0x08), (0x01, 0x08)],
+            high_current: &[(0x00, 0x10), (0x01, 0x10)],
+            pin_groups: &[],
+        },
+        ChipType::R => &Layout {
+            strings: 0x98,
+            drivers: &[(0x00, 0x08)],
+            high_current: &[(0x00, 0x04)],
+            pin_groups: &[],
+        },
+        ChipType::FT2232H => &Layout {
+            strings: 0x9A,
+            drivers: &[(0x00, 0x08), (0x01, 0x08)],
+            high_current: &[],
+            pin_groups: &[(0x0C, 0), (0x0C, 4), (0x0D, 0), (0x0D, 4)],
+        },
+        ChipType::FT4232H => &Layout {
+            strings: 0x9A,
+            drivers: &[(0x00, 0x08), (0x01, 0x08), (0x00, 0x80), (0x01, 0x80)],
+            high_current: &[],
+            pin_groups: &[(0x0C, 0), (0x0C, 4), (0x0D, 0), (0x0D, 4)],
+        },
+        ChipType::FT232H => &Layout {
+            strings: 0x9E,
+            drivers: &[(0x00, 0x10)],
+            high_current: &[],
+            pin_groups: &[(0x0C, 0), (0x0D, 0)],
+        },
+        ChipType::X => &Layout {
+            strings: 0xA0,
+            drivers: &[(0x00, 0x08)],
+            high_current: &[],
+            pin_groups: &[(0x0C, 0), (0x0C, 4)],
+        },
+    }
+}
+
+/// Type of memory storing the device configuration.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EepromType {
+    /// No EEPROM was detected.
+    ///
+    /// Either no EEPROM is attached, or the attached EEPROM is blank. The two cannot be told apart.
+    Absent,
+    /// The internal EEPROM of the FT232R, or the MTP memory of FT-X chips.
+    Internal,
+    /// An external 93C46 EEPROM (64 words).
+    Eeprom93C46,
+    /// An external 93C56 EEPROM (128 words).
+    Eeprom93C56,
+    /// An external 93C66 EEPROM (256 words).
+    Eeprom93C66,
+}
+
+/// Information about the EEPROM of a device.
+///
+/// This is returned by [`Ftdi::eeprom_info`].
+///
+/// [`Ftdi::eeprom_info`]: ../struct.Ftdi.html#method.eeprom_info
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct EepromInfo {
+    ty: EepromType,
+    size: u16,
+}
+
+impl EepromInfo {
+    pub(crate) fn internal(chip: ChipType) -> Option<Self> {
+        let size = match chip {
+            ChipType::R => 64,
+            // Only the configuration area of the MTP memory is accessible this way.
+            ChipType::X => 128,
+            _ => return None,
+        };
+        Some(Self {
+            ty: EepromType::Internal,
+            size,
+        })
+    }
+
+    /// Detects the type of an external EEPROM from the contents of the first 256 words.
+    ///
+    /// Smaller EEPROMs ignore the upper address bits, so their contents repeat.
+    pub(crate) fn detect(words: &[u16]) -> Self {
+        assert_eq!(words.len(), 256);
+        let ty = if words.iter().all(|&word| word == 0xFFFF) {
+            EepromType::Absent
+        } else if words[..128] != words[128..] {
+            EepromType::Eeprom93C66
+        } else if words[..64] != words[64..128] {
+            EepromType::Eeprom93C56
+        } else {
+            EepromType::Eeprom93C46
+        };
+        let size = match ty {
+            EepromType::Absent => 0,
+            EepromType::Eeprom93C46 => 64,
+            EepromType::Eeprom93C56 => 128,
+            EepromType::Internal | EepromType::Eeprom93C66 => 256,
+        };
+        Self { ty, size }
+    }
+
+    /// Returns the type of the EEPROM.
+    pub fn ty(&self) -> EepromType {
+        self.ty
+    }
+
+    /// Returns the size of the EEPROM in words, or 0 if no EEPROM was detected.
+    pub fn size_words(&self) -> u16 {
+        self.size
+    }
+}
+
+/// Driver to use for a port on Windows.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Driver {
+    /// FTDI's D2XX direct driver.
+    D2xx,
+    /// Virtual COM Port driver.
+    Vcp,
+}
+
+/// Output drive strength of a group of pins.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DriveStrength {
+    /// 4 mA.
+    Ma4 = 0,
+    /// 8 mA.
+    Ma8 = 1,
+    /// 12 mA.
+    Ma12 = 2,
+    /// 16 mA.
+    Ma16 = 3,
+}
+
+/// Settings of a port stored in the EEPROM.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PortConfig {
+    /// The driver to use for this port.
+    pub driver: Driver,
+    /// Whether the port's I/O pins use high current drive.
+    ///
+    /// This is only supported by FT2232C/D and FT232R chips. Newer chips configure the drive
+    /// strength per pin group instead.
+    pub high_current: bool,
+}
+
+/// Electrical settings of a group of pins.
+///
+/// The pin groups are:
+///
+/// * FT2232H: ADBUS, ACBUS, BDBUS, BCBUS
+/// * FT4232H: Port A, B, C and D
+/// * FT232H: ADBUS, ACBUS
+/// * FT-X: DBUS, CBUS
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PinGroup {
+    /// Output drive strength.
+    pub drive: DriveStrength,
+    /// Whether outputs use a slow slew rate.
+    pub slow_slew: bool,
+    /// Whether inputs have Schmitt triggers enabled.
+    pub schmitt: bool,
+}
+
+impl PinGroup {
+    fn decode(nibble: u8) -> Self {
+        Self {
+            drive: match nibble & 0x3 {
+                0 => DriveStrength::Ma4,
+                1 => DriveStrength::Ma8,
+                2 => DriveStrength::Ma12,
+                _ => DriveStrength::Ma16,
+            },
+            slow_slew: nibble & 0x4 != 0,
+            schmitt: nibble & 0x8 != 0,
+        }
+    }
+
+    fn encode(&self) -> u8 {
+        self.drive as u8 | (self.slow_slew as u8) << 2 | (self.schmitt as u8) << 3
+    }
+}
+
+/// Returns the word address of the checksum in an EEPROM image of `len` words.
+///
+/// External EEPROMs (93C46/56/66) store the checksum in their last word. The FT-X MTP memory
+/// always stores it at the end of the configuration area.
+fn checksum_addr(chip: ChipType, len: usize) -> usize {
+    match chip {
+        ChipType::X => X_CHECKSUM_ADDR,
+        _ => len - 1,
+    }
+}
+
+/// Computes the checksum of an EEPROM image.
+fn compute_checksum(chip: ChipType, words: &[u16]) -> u16 {
+    let end = checksum_addr(chip, words.len());
+    words[..end]
+        .iter()
+        .enumerate()
+        .filter(|&(addr, _)| chip != ChipType::X || !X_USER_AREA.contains(&addr))
+        .fold(CHECKSUM_SEED, |checksum, (_, &word)| {
+            (checksum ^ word).rotate_left(1)
+        })
+}
+
+fn lookup_model(model: &str) -> Result<&'static DeviceProps> {
+    prop::by_model(model).ok_or_else(|| {
+        Error::new(
+            ErrorKind::UnsupportedDevice,
+            format!("unknown model '{}'", model),
+        )
+    })
+}
+
+fn check_size(chip: ChipType, words: &[u16]) -> Result<()> {
+    if ![64, 128, 256].contains(&words.len()) || checksum_addr(chip, words.len()) >= words.len() {
+        return Err(Error::new(
+            ErrorKind::Eeprom,
+            format!("invalid EEPROM size of {} words", words.len()),
+        ));
+    }
+    Ok(())
+}
+
+/// Recomputes and stores the checksum of the EEPROM image `words` of a `model` device.
+///
+/// FTDI chips ignore EEPROM contents with an invalid checksum, so this has to be done after
+/// modifying an image.
+pub fn update_checksum(model: &str, words: &mut [u16]) -> Result<()> {
+    let chip = lookup_model(model)?.chip;
+    check_size(chip, words)?;
+    words[checksum_addr(chip, words.len())] = compute_checksum(chip, words);
+    Ok(())
+}
+
+/// Checks that the EEPROM image `words` of a `model` device has a valid checksum.
+///
+/// A checksum mismatch is reported as an error of kind `Eeprom`.
+pub fn verify_checksum(model: &str, words: &[u16]) -> Result<()> {
+    let chip = lookup_model(model)?.chip;
+    check_size(chip, words)?;
+    let addr = checksum_addr(chip, words.len());
+    let expected = compute_checksum(chip, words);
+    if words[addr] != expected {
+        return Err(Error::new(
+            ErrorKind::Eeprom,
+            format!(
+                "checksum mismatch at word 0x{:02x} (stored 0x{:04x}, expected 0x{:04x})",
+                addr, words[addr], expected
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Device configuration stored in the EEPROM.
+///
+/// The configuration is decoded from an EEPROM image with [`decode`], and can be turned back
+/// into an image with [`encode`]. Settings that are not represented by this type are carried over
+/// unchanged from the decoded image.
+///
+/// [`decode`]: #method.decode
+/// [`encode`]: #method.encode
+#[derive(Clone)]
+pub struct EepromConfig {
+    props: &'static DeviceProps,
+    /// The decoded image, as bytes.
+    image: Vec<u8>,
+
+    /// USB Vendor ID.
+    pub vendor_id: u16,
+    /// USB Product ID.
+    pub product_id: u16,
+    /// Manufacturer string.
+    pub manufacturer: String,
+    /// Product description string.
+    pub product: String,
+    /// Serial number string, or `None` if the device does not report a serial number.
+    pub serial: Option<String>,
+    /// Whether the device is self powered (otherwise, it is bus powered).
+    pub self_powered: bool,
+    /// Whether the device can wake up the host.
+    pub remote_wakeup: bool,
+    /// Maximum current drawn from the bus, in mA (at most 500).
+    pub max_current_ma: u16,
+    /// Per-port settings.
+    ///
+    /// This is empty for FT232AM and FT232BM chips, which have no per-port settings.
+    pub ports: Vec<PortConfig>,
+    /// Electrical settings of each pin group.
+    ///
+    /// This is empty for chips without configurable pin groups. See [`PinGroup`] for the groups
+    /// of each chip.
+    ///
+    /// [`PinGroup`]: struct.PinGroup.html
+    pub pin_groups: Vec<PinGroup>,
+}
+
+impl EepromConfig {
+    /// Decodes the EEPROM image of a device.
+    ///
+    /// `model` is the model name of the device, as returned by [`Ftdi::model`]. `words` is the
+    /// complete EEPROM image, which must be 64, 128 or 256 words long (FT-X images must be at
+    /// least 128 words long).
+    ///
+    /// The checksum is not checked, use [`verify_checksum`] for that.
+    ///
+    /// [`Ftdi::model`]: ../struct.Ftdi.html#method.model
+    /// [`verify_checksum`]: fn.verify_checksum.html
+    pub fn decode(model: &str, words: &[u16]) -> Result<Self> {
+        let props = lookup_model(model)?;
+        check_size(props.chip, words)?;
+        if words.iter().all(|&word| word == 0xFFFF) {
+            return Err(Error::new(ErrorKind::Eeprom, "EEPROM is blank"));
+        }
+
+        let image = words
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let layout = layout(props.chip);
+
+        let ports = layout
+            .drivers
+            .iter()
+            .enumerate()
+            .map(|(i, &(addr, mask))| PortConfig {
+                driver: if image[addr] & mask != 0 {
+                    Driver::Vcp
+                } else {
+                    Driver::D2xx
+                },
+                high_current: match layout.high_current.get(i) {
+                    Some(&(addr, mask)) => image[addr] & mask != 0,
+                    None => false,
+                },
+            })
+            .collect();
+        let pin_groups = layout
+            .pin_groups
+            .iter()
+            .map(|&(addr, shift)| PinGroup::decode(image[addr] >> shift & 0xF))
+            .collect();
+
+        let serial = if image[ADDR_CHIP_CONFIG] & CONFIG_USE_SERIAL != 0 {
+            Some(read_string(&image, ADDR_SERIAL)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            props,
+            vendor_id: u16::from_le_bytes([image[ADDR_VID], image[ADDR_VID + 1]]),
+            product_id: u16::from_le_bytes([image[ADDR_PID], image[ADDR_PID + 1]]),
+            manufacturer: read_string(&image, ADDR_MANUFACTURER)?,
+            product: read_string(&image, ADDR_PRODUCT)?,
+            serial,
+            self_powered: image[ADDR_ATTRIBUTES] & ATTR_SELF_POWERED != 0,
+            remote_wakeup: image[ADDR_ATTRIBUTES] & ATTR_REMOTE_WAKEUP != 0,
+            max_current_ma: u16::from(image[ADDR_MAX_POWER]) * 2,
+            ports,
+            pin_groups,
+            image,
+        })
+    }
+
+    /// Encodes the configuration into an EEPROM image.
+    ///
+    /// The returned image has the same size as the decoded one, and has a valid checksum. An error
+    /// of kind `Eeprom` is returned if the configuration is invalid for the device, or if the
+    /// strings do not fit into the EEPROM.
+    pub fn encode(&self) -> Result<Vec<u16>> {
+        let layout = layout(self.props.chip);
+        if self.ports.len() != layout.drivers.len() {
+            return Err(self.invalid(format!(
+                "{} EEPROM stores settings for {} ports, got {}",
+                self.props.model,
+                layout.drivers.len(),
+                self.ports.len()
+            )));
+        }
+        if self.pin_groups.len() != layout.pin_groups.len() {
+            return Err(self.invalid(format!(
+                "{} EEPROM stores settings for {} pin groups, got {}",
+                self.props.model,
+                layout.pin_groups.len(),
+                self.pin_groups.len()
+            )));
+        }
+        if layout.high_current.is_empty() && self.ports.iter().any(|port| port.high_current) {
+            return Err(self.invalid(format!(
+                "{} does not support high current drive",
+                self.props.model
+            )));
+        }
+        if self.max_current_ma > MAX_CURRENT_LIMIT {
+            return Err(self.invalid(format!(
+                "maximum current of {} mA exceeds the USB limit",
+                self.max_current_ma
+            )));
+        }
+
+        let mut image = self.image.clone();
+        image[ADDR_VID..ADDR_VID + 2].copy_from_slice(&self.vendor_id.to_le_bytes());
+        image[ADDR_PID..ADDR_PID + 2].copy_from_slice(&self.product_id.to_le_bytes());
+        image[ADDR_ATTRIBUTES] |= ATTR_RESERVED;
+        set_bits(
+            &mut image[ADDR_ATTRIBUTES],
+            ATTR_SELF_POWERED,
+            self.self_powered,
+        );
+        set_bits(
+            &mut image[ADDR_ATTRIBUTES],
+            ATTR_REMOTE_WAKEUP,
+            self.remote_wakeup,
+        );
+        image[ADDR_MAX_POWER] = self.max_current_ma.div_ceil(2) as u8;
+
+        for (i, port) in self.ports.iter().enumerate() {
+            let (addr, mask) = layout.drivers[i];
+            set_bits(&mut image[addr], mask, port.driver == Driver::Vcp);
+            if let Some(&(addr, mask)) = layout.high_current.get(i) {
+                set_bits(&mut image[addr], mask, port.high_current);
+            }
+        }
+        for (group, &(addr, shift)) in self.pin_groups.iter().zip(layout.pin_groups) {
+            image[addr] = image[addr] & !(0xF << shift) | group.encode() << shift;
+        }
+
+        set_bits(
+            &mut image[ADDR_CHIP_CONFIG],
+            CONFIG_USE_SERIAL,
+            self.serial.is_some(),
+        );
+
+        // String descriptors are stored back to back, in front of the checksum.
+        let mask = string_ptr_mask(image.len());
+        let end = cmp::min(
+            checksum_addr(self.props.chip, image.len() / 2) * 2,
+            mask + 1,
+        );
+        let mut offset = usize::from(layout.strings) & mask;
+        let strings = [
+            (ADDR_MANUFACTURER, Some(&self.manufacturer)),
+            (ADDR_PRODUCT, Some(&self.product)),
+            (ADDR_SERIAL, self.serial.as_ref()),
+        ];
+        for &(addr, string) in &strings {
+            let string = match string {
+                Some(string) => string,
+                None => {
+                    image[addr] = 0;
+                    image[addr + 1] = 0;
+                    continue;
+                }
+            };
+
+            let mut descriptor = vec![0, DESCRIPTOR_STRING];
+            descriptor.extend(string.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()));
+            if descriptor.len() > 0xFF || offset + descriptor.len() > end {
+                return Err(self.invalid("strings do not fit into the EEPROM"));
+            }
+            descriptor[0] = descriptor.len() as u8;
+
+            image[addr] = offset as u8 | 0x80;
+            image[addr + 1] = descriptor.len() as u8;
+            image[offset..offset + descriptor.len()].copy_from_slice(&descriptor);
+            offset += descriptor.len();
+        }
+
+        let mut words = image
+            .chunks(2)
+            .map(|word| u16::from_le_bytes([word[0], word[1]]))
+            .collect::<Vec<_>>();
+        let addr = checksum_addr(self.props.chip, words.len());
+        words[addr] = compute_checksum(self.props.chip, &words);
+        Ok(words)
+    }
+
+    /// Returns the function assigned to CBUS pin `pin`.
+    ///
+    /// `F` selects the CBUS functions of the chip: [`Ft232rCbus`] for FT232R, [`Ft232hCbus`] for
+    /// FT232H (where `pin` is the ACBUS pin number) and [`FtxCbus`] for FT-X devices. An error of
+    /// kind `Eeprom` is returned if `F` does not belong to the device, if it has no such pin, or if
+    /// the EEPROM contains an unknown function code.
+    ///
+    /// [`Ft232rCbus`]: enum.Ft232rCbus.html
+    /// [`Ft232hCbus`]: enum.Ft232hCbus.html
+    /// [`FtxCbus`]: enum.FtxCbus.html
+    pub fn cbus<F: CbusFunction>(&self, pin: u8) -> Result<F> {
+        let (addr, shift, mask) = self.cbus_slot::<F>(pin)?;
+        let code = self.image[addr] >> shift & mask;
+        F::from_code(code).ok_or_else(|| {
+            self.invalid(format!(
+                "unknown function 0x{:x} assigned to CBUS{}",
+                code, pin
+            ))
+        })
+    }
+
+    /// Assigns `function` to CBUS pin `pin`.
+    ///
+    /// The change is written to the EEPROM with the rest of the configuration. An error of kind
+    /// `Eeprom` is returned if `F` does not belong to the device, if it has no such pin, or if the
+    /// pin does not support `function`.
+    pub fn set_cbus<F: CbusFunction>(&mut self, pin: u8, function: F) -> Result<()> {
+        let (addr, shift, mask) = self.cbus_slot::<F>(pin)?;
+        if !function.supported_on(pin) {
+            return Err(self.invalid(format!(
+                "{:?} is not supported on CBUS{} of the {}",
+                function, pin, self.props.model
+            )));
+        }
+
+        self.image[addr] = self.image[addr] & !(mask << shift) | function.code() << shift;
+        Ok(())
+    }
+
+    fn cbus_slot<F: CbusFunction>(&self, pin: u8) -> Result<(usize, u8, u8)> {
+        if F::MODEL != self.props.model {
+            return Err(self.invalid(format!(
+                "{} CBUS functions cannot be used with an {}",
+                F::MODEL,
+                self.props.model
+            )));
+        }
+
+        F::PINS
+            .get(usize::from(pin))
+            .copied()
+            .ok_or_else(|| self.invalid(format!("{} has no CBUS{} pin", self.props.model, pin)))
+    }
+
+    /// Returns the model name of the device this configuration belongs to.
+    pub fn model(&self) -> &'static str {
+        self.props.model
+    }
+
+    fn invalid(&self, msg: impl Into<String>) -> Error {
+        Error::new(ErrorKind::Eeprom, msg.into())
+    }
+}
+
+impl fmt::Debug for EepromConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EepromConfig")
+            .field("model", &self.props.model)
+            .field("vendor_id", &self.vendor_id)
+            .field("product_id", &self.product_id)
+            .field("manufacturer", &self.manufacturer)
+            .field("product", &self.product)
+            .field("serial", &self.serial)
+            .field("self_powered", &self.self_powered)
+            .field("remote_wakeup", &self.remote_wakeup)
+            .field("max_current_ma", &self.max_current_ma)
+            .field("ports", &self.ports)
+            .field("pin_groups", &self.pin_groups)
+            .finish()
+    }
+}
+
+/// A backup of the EEPROM contents of a device.
+///
+/// Backups are created with [`Ftdi::backup_eeprom`] and restored with [`Ftdi::restore_eeprom`].
+/// They can be stored in a text format that looks like this:
+///
+/// ```text
+/// rftdi-eeprom-backup 1
+/// model: FT232R
+/// size: 64
+/// serial: A1234567
+///
+/// 0000: 4000 0403 6001 0600 2da0 0008 0000 0a98
+/// 0008: 20a2 12c2 1023 0005 030a 0046 0054 0044
+/// ...
+/// ```
+///
+/// The `serial` line is omitted if the device has no serial number.
+///
+/// [`Ftdi::backup_eeprom`]: ../struct.Ftdi.html#method.backup_eeprom
+/// [`Ftdi::restore_eeprom`]: ../struct.Ftdi.html#method.restore_eeprom
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EepromBackup {
+    model: String,
+    serial: Option<String>,
+    words: Vec<u16>,
+}
+
+const BACKUP_MAGIC: &str = "rftdi-eeprom-backup 1";
+const BACKUP_WORDS_PER_LINE: usize = 8;
+
+impl EepromBackup {
+    pub(crate) fn new(model: &str, serial: Option<String>, words: Vec<u16>) -> Self {
+        Self {
+            model: model.to_string(),
+            serial,
+            words,
+        }
+    }
+
+    /// Returns the model name of the device the backup was made from.
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Returns the serial number of the device the backup was made from.
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
+    /// Returns the EEPROM contents.
+    pub fn words(&self) -> &[u16] {
+        &self.words
+    }
+
+    /// Writes the backup in text format.
+    pub fn write_to(&self, mut writer: impl io::Write) -> io::Result<()> {
+        writeln!(writer, "{}", BACKUP_MAGIC)?;
+        writeln!(writer, "model: {}", self.model)?;
+        writeln!(writer, "size: {}", self.words.len())?;
+        if let Some(serial) = &self.serial {
+            writeln!(writer, "serial: {}", serial)?;
+        }
+        writeln!(writer)?;
+
+        for (i, line) in self.words.chunks(BACKUP_WORDS_PER_LINE).enumerate() {
+            write!(writer, "{:04x}:", i * BACKUP_WORDS_PER_LINE)?;
+            for word in line {
+                write!(writer, " {:04x}", word)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a backup in text format.
+    ///
+    /// Malformed backups are reported as errors of kind `Eeprom`.
+    pub fn read_from(reader: impl io::Read) -> Result<Self> {
+        let invalid =
+            |msg: String| Error::new(ErrorKind::Eeprom, format!("invalid backup: {}", msg));
+
+        let mut lines = io::BufReader::new(reader).lines();
+        let mut next_line = || -> Result<Option<String>> {
+            lines
+                .next()
+                .transpose()
+                .map_err(|e| Error::new(ErrorKind::Other, e))
+        };
+
+        if next_line()?.as_deref().map(str::trim) != Some(BACKUP_MAGIC) {
+            return Err(invalid(String::from("missing header")));
+        }
+
+        let (mut model, mut size, mut serial) = (None, None, None);
+        while let Some(line) = next_line()? {
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            match line.split_once(':') {
+                Some(("model", value)) => model = Some(value.trim().to_string()),
+                Some(("serial", value)) => serial = Some(value.trim().to_string()),
+                Some(("size", value)) => {
+                    let value = value.trim();
+                    size = Some(
+                        value
+                            .parse::<usize>()
+                            .map_err(|_| invalid(format!("invalid size '{}'", value)))?,
+                    );
+                }
+                _ => return Err(invalid(format!("unexpected line '{}'", line))),
+            }
+        }
+        let model = model.ok_or_else(|| invalid(String::from("missing model")))?;
+        let size = size.ok_or_else(|| invalid(String::from("missing size")))?;
+        if !is_valid_size(size) {
+            return Err(invalid(format!("unsupported EEPROM size {}", size)));
+        }
+
+        let mut words = Vec::with_capacity(size);
+        while let Some(line) = next_line()? {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (addr, data) = line
+                .split_once(':')
+                .ok_or_else(|| invalid(format!("unexpected line '{}'", line)))?;
+            if usize::from_str_radix(addr.trim(), 16).ok() != Some(words.len()) {
+                return Err(invalid(format!("unexpected address '{}'", addr)));
+            }
+            for word in data.split_whitespace() {
+                let word = u16::from_str_radix(word, 16)
+                    .map_err(|_| invalid(format!("invalid word '{}'", word)))?;
+                words.push(word);
+            }
+        }
+        if words.len() != size {
+            return Err(invalid(format!(
+                "expected {} words, found {}",
+                size,
+                words.len()
+            )));
+        }
+
+        Ok(Self {
+            model,
+            serial,
+            words,
+        })
+    }
+}
+
+/// Returns whether `words` is the size of an EEPROM supported by any FTDI chip.
+pub(crate) fn is_valid_size(words: usize) -> bool {
+    matches!(words, 64 | 128 | 256)
+}
+
+fn set_bits(byte: &mut u8, mask: u8, value: bool) {
+    if value {
+        *byte |= mask;
+    } else {
+        *byte &= !mask;
+    }
+}
+
+/// Returns the mask to apply to string descriptor pointers in an image of `len` Bytes.
+///
+/// Pointers are only 8 bits wide, so strings have to be placed in the first 256 Bytes, even on
+/// larger EEPROMs.
+fn string_ptr_mask(len: usize) -> usize {
+    cmp::min(len, 0x100) - 1
+}
+
+/// Reads the string descriptor whose pointer and length are stored at `addr`.
+fn read_string(image: &[u8], addr: usize) -> Result<String> {
+    let offset = usize::from(image[addr]) & string_ptr_mask(image.len());
+    let len = usize::from(image[addr + 1]);
+    if len == 0 {
+        return Ok(String::new());
+    }
+
+    if len < 2 || len % 2 != 0 || offset + len > image.len() {
+        return Err(Error::new(
+            ErrorKind::Eeprom,
+            format!("invalid string descriptor at 0x{:02x}", offset),
+        ));
+    }
+    let descriptor = &image[offset..offset + len];
+    if descriptor[1] != DESCRIPTOR_STRING {
+        return Err(Error::new(
+            ErrorKind::Eeprom,
+            format!("invalid string descriptor type at 0x{:02x}", offset),
+        ));
+    }
+
+    let utf16 = descriptor[2..]
+        .chunks(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16(&utf16).map_err(|e| Error::new(ErrorKind::Eeprom, e))
+}
@@ -0,0 +1,296 @@
+//! CBUS pin functions.
+
+use std::fmt;
+
+use sealed::Sealed as _;
+
+pub(super) mod sealed {
+    pub trait Sealed: Copy + PartialEq {
+        /// Model name of the chip these functions belong to.
+        const MODEL: &'static str;
+
+        /// Byte address, bit offset and mask of each CBUS pin's function in the EEPROM.
+        const PINS: &'static [(usize, u8, u8)];
+
+        /// The function that makes a pin available in CBUS bit-bang mode.
+        const IO_MODE: Self;
+
+        /// The CBUS pins controlled by bits 0-3 in CBUS bit-bang mode.
+        const BITBANG_PINS: &'static [u8];
+
+        fn from_code(code: u8) -> Option<Self>;
+
+        fn code(self) -> u8;
+
+        /// Returns whether the function can be assigned to CBUS pin `pin`.
+        fn supported_on(self, pin: u8) -> bool;
+    }
+}
+
+/// Functions that can be assigned to the CBUS pins of a chip.
+///
+/// This is implemented by [`Ft232rCbus`], [`Ft232hCbus`] and [`FtxCbus`], and used with
+/// [`EepromConfig::cbus`] and [`EepromConfig::set_cbus`].
+///
+/// [`Ft232rCbus`]: enum.Ft232rCbus.html
+/// [`Ft232hCbus`]: enum.Ft232hCbus.html
+/// [`FtxCbus`]: enum.FtxCbus.html
+/// [`EepromConfig::cbus`]: struct.EepromConfig.html#method.cbus
+/// [`EepromConfig::set_cbus`]: struct.EepromConfig.html#method.set_cbus
+pub trait CbusFunction: sealed::Sealed + fmt::Debug {}
+
+/// Returns which of the 4 CBUS bit-bang pins of a `model` device are configured for I/O mode.
+///
+/// `image` holds (at least) the first 32 Bytes of the EEPROM image. Returns `None` if the device
+/// does not support CBUS bit-bang mode.
+pub(crate) fn io_mode_pins(model: &str, image: &[u8]) -> Option<u8> {
+    fn mask<F: CbusFunction>(image: &[u8]) -> u8 {
+        F::BITBANG_PINS
+            .iter()
+            .enumerate()
+            .filter(|&(_, &pin)| {
+                let (addr, shift, mask) = F::PINS[usize::from(pin)];
+                F::from_code(image[addr] >> shift & mask) == Some(F::IO_MODE)
+            })
+            .fold(0, |pins, (bit, _)| pins | 1 << bit)
+    }
+
+    if model == Ft232rCbus::MODEL {
+        Some(mask::<Ft232rCbus>(image))
+    } else if model == Ft232hCbus::MODEL {
+        Some(mask::<Ft232hCbus>(image))
+    } else if model == FtxCbus::MODEL {
+        Some(mask::<FtxCbus>(image))
+    } else {
+        None
+    }
+}
+
+macro_rules! cbus_functions {
+    (
+        $( #[$attr:meta] )*
+        pub enum $name:ident {
+            $( $( #[$vattr:meta] )* $variant:ident = $code:literal, )+
+        }
+    ) => {
+        $( #[$attr] )*
+        #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+        pub enum $name {
+            $( $( #[$vattr] )* $variant = $code, )+
+        }
+
+        impl CbusFunction for $name {}
+
+        impl $name {
+            fn decode(code: u8) -> Option<Self> {
+                match code {
+                    $( $code => Some($name::$variant), )+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+cbus_functions! {
+    /// Functions of the CBUS0-CBUS4 pins of the FT232R.
+    ///
+    /// CBUS4 does not support `IoMode`, `BitbangWr` and `BitbangRd`.
+    pub enum Ft232rCbus {
+        /// Transmit enable for RS485 transceivers.
+        TxDen = 0x0,
+        /// Low when the device is configured by USB and not suspended (PWREN#).
+        PwrEn = 0x1,
+        /// Pulses low when receiving data (RXLED#).
+        RxLed = 0x2,
+        /// Pulses low when transmitting data (TXLED#).
+        TxLed = 0x3,
+        /// Pulses low when transmitting or receiving data (TX&RXLED#).
+        TxRxLed = 0x4,
+        /// Low when the device is in USB suspend mode (SLEEP#).
+        Sleep = 0x5,
+        /// 48 MHz clock output.
+        Clk48 = 0x6,
+        /// 24 MHz clock output.
+        Clk24 = 0x7,
+        /// 12 MHz clock output.
+        Clk12 = 0x8,
+        /// 6 MHz clock output.
+        Clk6 = 0x9,
+        /// GPIO, controlled in CBUS bit-bang mode.
+        IoMode = 0xA,
+        /// Synchronous bit-bang write strobe.
+        BitbangWr = 0xB,
+        /// Synchronous bit-bang read strobe.
+        BitbangRd = 0xC,
+    }
+}
+
+impl sealed::Sealed for Ft232rCbus {
+    const MODEL: &'static str = "FT232R";
+    const PINS: &'static [(usize, u8, u8)] = &[
+        (0x14, 0, 0xF),
+        (0x14, 4, 0xF),
+        (0x15, 0, 0xF),
+        (0x15, 4, 0xF),
+        (0x16, 0, 0xF),
+    ];
+    const IO_MODE: Self = Ft232rCbus::IoMode;
+    const BITBANG_PINS: &'static [u8] = &[0, 1, 2, 3];
+
+    fn from_code(code: u8) -> Option<Self> {
+        Self::decode(code)
+    }
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn supported_on(self, pin: u8) -> bool {
+        match self {
+            Ft232rCbus::IoMode | Ft232rCbus::BitbangWr | Ft232rCbus::BitbangRd => pin != 4,
+            _ => true,
+        }
+    }
+}
+
+cbus_functions! {
+    /// Functions of the ACBUS0-ACBUS9 pins of the FT232H.
+    ///
+    /// `IoMode` is only supported on ACBUS5, ACBUS6, ACBUS8 and ACBUS9.
+    pub enum Ft232hCbus {
+        /// High impedance input.
+        Tristate = 0x0,
+        /// Pulses low when transmitting data (TXLED#).
+        TxLed = 0x1,
+        /// Pulses low when receiving data (RXLED#).
+        RxLed = 0x2,
+        /// Pulses low when transmitting or receiving data (TX&RXLED#).
+        TxRxLed = 0x3,
+        /// Low when the device is configured by USB and not suspended (PWREN#).
+        PwrEn = 0x4,
+        /// Low when the device is in USB suspend mode (SLEEP#).
+        Sleep = 0x5,
+        /// Driven low.
+        Drive0 = 0x6,
+        /// Driven high.
+        Drive1 = 0x7,
+        /// GPIO, controlled in CBUS bit-bang mode.
+        IoMode = 0x8,
+        /// Transmit enable for RS485 transceivers.
+        TxDen = 0x9,
+        /// 30 MHz clock output.
+        Clk30 = 0xA,
+        /// 15 MHz clock output.
+        Clk15 = 0xB,
+        /// 7.5 MHz clock output.
+        Clk7_5 = 0xC,
+    }
+}
+
+impl sealed::Sealed for Ft232hCbus {
+    const MODEL: &'static str = "FT232H";
+    const PINS: &'static [(usize, u8, u8)] = &[
+        (0x18, 0, 0xF),
+        (0x18, 4, 0xF),
+        (0x19, 0, 0xF),
+        (0x19, 4, 0xF),
+        (0x1A, 0, 0xF),
+        (0x1A, 4, 0xF),
+        (0x1B, 0, 0xF),
+        (0x1B, 4, 0xF),
+        (0x1C, 0, 0xF),
+        (0x1C, 4, 0xF),
+    ];
+    const IO_MODE: Self = Ft232hCbus::IoMode;
+    const BITBANG_PINS: &'static [u8] = &[5, 6, 8, 9];
+
+    fn from_code(code: u8) -> Option<Self> {
+        Self::decode(code)
+    }
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn supported_on(self, pin: u8) -> bool {
+        match self {
+            Ft232hCbus::IoMode => [5, 6, 8, 9].contains(&pin),
+            _ => true,
+        }
+    }
+}
+
+cbus_functions! {
+    /// Functions of the CBUS0-CBUS3 pins of FT-X chips.
+    pub enum FtxCbus {
+        /// High impedance input.
+        Tristate = 0x00,
+        /// Pulses low when transmitting data (TXLED#).
+        TxLed = 0x01,
+        /// Pulses low when receiving data (RXLED#).
+        RxLed = 0x02,
+        /// Pulses low when transmitting or receiving data (TX&RXLED#).
+        TxRxLed = 0x03,
+        /// Low when the device is configured by USB and not suspended (PWREN#).
+        PwrEn = 0x04,
+        /// Low when the device is in USB suspend mode (SLEEP#).
+        Sleep = 0x05,
+        /// Driven low.
+        Drive0 = 0x06,
+        /// Driven high.
+        Drive1 = 0x07,
+        /// GPIO, controlled in CBUS bit-bang mode.
+        IoMode = 0x08,
+        /// Transmit enable for RS485 transceivers.
+        TxDen = 0x09,
+        /// 24 MHz clock output.
+        Clk24 = 0x0A,
+        /// 12 MHz clock output.
+        Clk12 = 0x0B,
+        /// 6 MHz clock output.
+        Clk6 = 0x0C,
+        /// High when a battery charger is detected (BCD Charger).
+        BcdCharger = 0x0D,
+        /// Low when a battery charger is detected (BCD Charger#).
+        BcdChargerN = 0x0E,
+        /// I²C transmit buffer empty.
+        I2cTxe = 0x0F,
+        /// I²C receive buffer full.
+        I2cRxf = 0x10,
+        /// Input detecting the presence of VBUS.
+        VbusSense = 0x11,
+        /// Synchronous bit-bang write strobe.
+        BitbangWr = 0x12,
+        /// Synchronous bit-bang read strobe.
+        BitbangRd = 0x13,
+        /// Toggles on every USB start of frame.
+        TimeStamp = 0x14,
+        /// Low when the device is in USB suspend mode, without affecting other pins (Keep_Awake#).
+        KeepAwake = 0x15,
+    }
+}
+
+impl sealed::Sealed for FtxCbus {
+    const MODEL: &'static str = "FT-X";
+    const PINS: &'static [(usize, u8, u8)] = &[
+        (0x1A, 0, 0xFF),
+        (0x1B, 0, 0xFF),
+        (0x1C, 0, 0xFF),
+        (0x1D, 0, 0xFF),
+    ];
+    const IO_MODE: Self = FtxCbus::IoMode;
+    const BITBANG_PINS: &'static [u8] = &[0, 1, 2, 3];
+
+    fn from_code(code: u8) -> Option<Self> {
+        Self::decode(code)
+    }
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn supported_on(self, _pin: u8) -> bool {
+        true
+    }
+}
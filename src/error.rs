@@ -1,10 +1,11 @@
-use std::{error, fmt};
+use std::{error, fmt, io};
 
 /// The error type used by this library.
 #[derive(Debug)]
 pub struct Error {
     kind: ErrorKind,
     inner: Option<Box<dyn error::Error + Send + Sync>>,
+    bytes_written: Option<usize>,
 }
 
 /// List of specific kinds of errors that may occur when using this library.
@@ -13,10 +14,19 @@ pub struct Error {
 pub enum ErrorKind {
     /// A USB I/O error was encountered.
     ///
-    /// This usually indicates that there is a permission problem, that a driver or another
-    /// application is using the device, or that the device was unplugged.
+    /// This usually indicates that there is a permission problem, or that a driver or another
+    /// application is using the device.
     Usb,
 
+    /// The device was disconnected.
+    ///
+    /// Once this is returned, the `Ftdi` and all of its ports are unusable and should be dropped.
+    /// If the device comes back (eg. after re-enumerating), it has to be opened again.
+    Disconnected,
+
+    /// A USB operation did not complete within the configured timeout.
+    Timeout,
+
     /// Multiple matching devices were found.
     MultipleDevicesFound,
 
@@ -29,52 +39,106 @@ pub enum ErrorKind {
     /// that a non-FTDI device was opened.
     UnsupportedDevice,
 
+    /// An EEPROM operation failed.
+    ///
+    /// This is returned if the EEPROM contents are invalid, if a configuration does not fit into
+    /// the EEPROM, or if an address is out of range.
+    Eeprom,
+
+    /// The MPSSE engine rejected a command.
+    ///
+    /// The error's `source` is an [`mpsse::BadCommand`] holding the offending opcode.
+    ///
+    /// [`mpsse::BadCommand`]: mpsse/struct.BadCommand.html
+    BadCommand,
+
+    /// An I²C device did not acknowledge its address or a data Byte.
+    ///
+    /// The error's `source` is an [`i2c::Nack`] describing the Byte that was not acknowledged.
+    ///
+    /// [`i2c::Nack`]: i2c/enum.Nack.html
+    Nack,
+
     /// Other errors that don't fit the other variants.
     Other,
 }
 
 impl Error {
-    pub(crate) fn new(
-        kind: ErrorKind,
-        inner: impl Into<Box<dyn error::Error + Send + Sync>>,
-    ) -> Self {
+    /// Creates an error of the given kind, wrapping an underlying error or message.
+    ///
+    /// This is meant for use by custom [`Backend`] implementations.
+    ///
+    /// [`Backend`]: backend/trait.Backend.html
+    pub fn new(kind: ErrorKind, inner: impl Into<Box<dyn error::Error + Send + Sync>>) -> Self {
         Self {
             kind,
             inner: Some(inner.into()),
+            bytes_written: None,
         }
     }
 
+    #[cfg(feature = "rusb")]
     pub(crate) fn usb(inner: rusb::Error) -> Self {
+        let kind = match inner {
+            rusb::Error::Timeout => ErrorKind::Timeout,
+            rusb::Error::NoDevice => ErrorKind::Disconnected,
+            _ => ErrorKind::Usb,
+        };
         Self {
-            kind: ErrorKind::Usb,
+            kind,
             inner: Some(Box::new(inner)),
+            bytes_written: None,
         }
     }
 
-    pub(crate) fn from_kind(kind: ErrorKind) -> Self {
-        Self { kind, inner: None }
+    /// Creates an error of the given kind without any further information.
+    pub fn from_kind(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            inner: None,
+            bytes_written: None,
+        }
     }
 
     pub(crate) fn other(msg: impl Into<Box<dyn error::Error + Send + Sync>>) -> Self {
         Self {
             kind: ErrorKind::Other,
             inner: Some(msg.into()),
+            bytes_written: None,
         }
     }
 
+    pub(crate) fn with_bytes_written(mut self, n: usize) -> Self {
+        self.bytes_written = Some(n);
+        self
+    }
+
     /// Returns the `ErrorKind` most closely describing this error.
     pub fn kind(&self) -> ErrorKind {
         self.kind
     }
+
+    /// Returns the number of Bytes that were successfully written before the error occurred.
+    ///
+    /// This is only set by operations that may fail after partially completing, like
+    /// `Port::write_all`.
+    pub fn bytes_written(&self) -> Option<usize> {
+        self.bytes_written
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let msg = match self.kind {
             ErrorKind::Usb => "USB error",
+            ErrorKind::Disconnected => "device disconnected",
+            ErrorKind::Timeout => "operation timed out",
             ErrorKind::MultipleDevicesFound => "multiple matching devices found",
             ErrorKind::NoDeviceFound => "no matching devices found",
             ErrorKind::UnsupportedDevice => "device is not supported by rftdi",
+            ErrorKind::Eeprom => "EEPROM error",
+            ErrorKind::BadCommand => "MPSSE command rejected",
+            ErrorKind::Nack => "I²C transfer not acknowledged",
             ErrorKind::Other => "other error",
         };
 
@@ -91,3 +155,14 @@ impl error::Error for Error {
         self.inner.as_ref().map(|e| &**e as &dyn error::Error)
     }
 }
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e.kind {
+            ErrorKind::Timeout => io::ErrorKind::TimedOut,
+            ErrorKind::Disconnected => io::ErrorKind::NotConnected,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, e)
+    }
+}
@@ -0,0 +1,256 @@
+//! Notifications about devices being connected and disconnected.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rusb::UsbContext;
+
+use crate::{DeviceInfo, DeviceSelector, Error, Result};
+
+type Device = rusb::Device<rusb::GlobalContext>;
+
+/// How often the device list is checked when libusb does not support hotplug notifications.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Timeout for handling libusb events, after which the watcher thread checks whether it should
+/// stop.
+const EVENT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A hotplug event reported by a [`HotplugWatcher`].
+///
+/// [`HotplugWatcher`]: struct.HotplugWatcher.html
+#[derive(Debug)]
+pub enum HotplugEvent {
+    /// A matching device was connected.
+    Arrived(DeviceInfo),
+
+    /// A previously reported device was disconnected.
+    ///
+    /// The `DeviceInfo` is the one reported when the device arrived. Any `Ftdi` or `Port` opened
+    /// from it will now fail with [`ErrorKind::Disconnected`].
+    ///
+    /// [`ErrorKind::Disconnected`]: enum.ErrorKind.html#variant.Disconnected
+    Left(DeviceInfo),
+}
+
+/// Watches for devices matching a [`DeviceSelector`] being connected or disconnected.
+///
+/// A background thread receives libusb hotplug notifications, or periodically polls the list of
+/// devices if the platform does not support them. A device that re-enumerates (eg. after a
+/// firmware update) is reported as leaving and then arriving again.
+///
+/// Devices already connected when the watcher is created are not reported as arriving, but
+/// their disconnection is. Use [`list_devices`] to find them.
+///
+/// Since matching the serial number or product string requires opening the device, devices
+/// that cannot be opened by this process never match selectors using these criteria.
+///
+/// [`DeviceSelector`]: struct.DeviceSelector.html
+/// [`list_devices`]: fn.list_devices.html
+#[derive(Debug)]
+pub struct HotplugWatcher {
+    events: mpsc::Receiver<HotplugEvent>,
+    stop: Arc<AtomicBool>,
+    registration: Option<rusb::Registration>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HotplugWatcher {
+    /// Starts watching for devices matching `selector`.
+    pub fn new(selector: DeviceSelector) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut tracker = Tracker {
+            selector,
+            devices: HashMap::new(),
+            events: tx,
+        };
+        let initial = rusb::devices().map_err(Error::usb)?;
+        for device in initial.iter() {
+            tracker.track(device, false);
+        }
+
+        let (registration, notifications) = if rusb::has_hotplug() {
+            let (tx, rx) = mpsc::channel();
+            let callback = Box::new(Callback { notifications: tx });
+            match rusb::GlobalContext::default().register_callback(None, None, None, callback) {
+                Ok(registration) => (Some(registration), Some(rx)),
+                Err(e) => {
+                    log::warn!(
+                        "failed to register hotplug callback, polling instead: {}",
+                        e
+                    );
+                    (None, None)
+                }
+            }
+        } else {
+            log::debug!("libusb does not support hotplug, polling instead");
+            (None, None)
+        };
+
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name(String::from("rftdi hotplug"))
+            .spawn(move || match notifications {
+                Some(notifications) => tracker.run_notified(notifications, &thread_stop),
+                None => tracker.run_polling(&thread_stop),
+            })
+            .map_err(|e| Error::other(format!("failed to spawn hotplug thread: {}", e)))?;
+
+        Ok(Self {
+            events,
+            stop,
+            registration,
+            thread: Some(thread),
+        })
+    }
+
+    /// Blocks until the next event occurs.
+    pub fn recv(&self) -> HotplugEvent {
+        // The watcher thread only exits when `self` is dropped.
+        self.events.recv().expect("hotplug thread died")
+    }
+
+    /// Waits up to `timeout` for the next event.
+    ///
+    /// Returns `None` if no event occurred within `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<HotplugEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Returns the next event if one is pending, without blocking.
+    pub fn try_recv(&self) -> Option<HotplugEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Drop for HotplugWatcher {
+    fn drop(&mut self) {
+        if let Some(registration) = self.registration.take() {
+            // Note that rusb leaks the `Callback` here, which only holds a channel sender.
+            rusb::GlobalContext::default().unregister_callback(registration);
+        }
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// libusb hotplug callback, forwarding notifications to the watcher thread.
+///
+/// Devices must not be opened from within the callback, so matching happens on the watcher
+/// thread.
+struct Callback {
+    notifications: mpsc::Sender<(Device, bool)>,
+}
+
+impl rusb::Hotplug<rusb::GlobalContext> for Callback {
+    fn device_arrived(&mut self, device: Device) {
+        self.notifications.send((device, true)).ok();
+    }
+
+    fn device_left(&mut self, device: Device) {
+        self.notifications.send((device, false)).ok();
+    }
+}
+
+/// Tracks connected devices and reports matching ones.
+struct Tracker {
+    selector: DeviceSelector,
+    /// All known devices by bus number and address, and their info if they match the selector.
+    devices: HashMap<(u8, u8), Option<DeviceInfo>>,
+    events: mpsc::Sender<HotplugEvent>,
+}
+
+impl Tracker {
+    fn run_notified(mut self, notifications: mpsc::Receiver<(Device, bool)>, stop: &AtomicBool) {
+        let context = rusb::GlobalContext::default();
+        while !stop.load(Ordering::Relaxed) {
+            if let Err(e) = context.handle_events(Some(EVENT_TIMEOUT)) {
+                log::error!("failed to handle libusb events: {}", e);
+                thread::sleep(EVENT_TIMEOUT);
+            }
+
+            for (device, arrived) in notifications.try_iter() {
+                if arrived {
+                    self.track(device, true);
+                } else {
+                    self.untrack(device.bus_number(), device.address());
+                }
+            }
+        }
+    }
+
+    fn run_polling(mut self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            thread::sleep(POLL_INTERVAL);
+
+            let list = match rusb::devices() {
+                Ok(list) => list,
+                Err(e) => {
+                    log::error!("failed to list devices: {}", e);
+                    continue;
+                }
+            };
+
+            let connected = list
+                .iter()
+                .map(|device| (device.bus_number(), device.address()))
+                .collect::<Vec<_>>();
+            let gone = self
+                .devices
+                .keys()
+                .filter(|key| !connected.contains(key))
+                .copied()
+                .collect::<Vec<_>>();
+            for (bus, address) in gone {
+                self.untrack(bus, address);
+            }
+
+            for device in list.iter() {
+                if !self
+                    .devices
+                    .contains_key(&(device.bus_number(), device.address()))
+                {
+                    self.track(device, true);
+                }
+            }
+        }
+    }
+
+    fn track(&mut self, device: Device, report: bool) {
+        let key = (device.bus_number(), device.address());
+        let info = if self.selector.matches_device(&device) {
+            match device.device_descriptor() {
+                Ok(descr) => Some(DeviceInfo::new(device, &descr)),
+                Err(e) => {
+                    log::debug!("failed to read device descriptor of {:?}: {}", device, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        match &info {
+            Some(info) if report => {
+                log::debug!("device arrived: {}", info);
+                self.events.send(HotplugEvent::Arrived(info.clone())).ok();
+            }
+            _ => {}
+        }
+        self.devices.insert(key, info);
+    }
+
+    fn untrack(&mut self, bus: u8, address: u8) {
+        if let Some(Some(info)) = self.devices.remove(&(bus, address)) {
+            log::debug!("device left: {}", info);
+            self.events.send(HotplugEvent::Left(info)).ok();
+        }
+    }
+}
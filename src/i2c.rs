@@ -0,0 +1,401 @@
+//! I²C master using the MPSSE.
+//!
+//! [`I2c`] drives SCL on ADBUS0 and SDA on ADBUS1. ADBUS2 is used to read SDA and has to be
+//! connected to ADBUS1. Both lines need external pull-up resistors.
+//!
+//! I²C requires 3-phase clocking, so only `-H` chips are supported. The FT232H's open-drain mode
+//! is used to drive the bus. On other chips, open-drain outputs are emulated by switching SDA to
+//! an input instead of driving it high, which requires clocking out data one bit at a time.
+//!
+//! Every method of `I2c` that talks to a device costs one USB round trip. To perform several
+//! transfers in a single round trip, queue them in a [`Batch`]:
+//!
+//! ```no_run
+//! use rftdi::bitmode::Mpsse;
+//! use rftdi::i2c::I2c;
+//! use rftdi::Ftdi;
+//!
+//! # fn main() -> rftdi::Result<()> {
+//! let ftdi = Ftdi::open_unique()?;
+//! let port = ftdi.open_port(0)?.into_mode::<Mpsse>()?;
+//! let mut i2c = I2c::new(port)?;
+//! i2c.set_clock(400_000)?;
+//!
+//! let mut batch = i2c.batch();
+//! batch.write(0x50, &[0x00, 0x10, 0xAB]);
+//! let temperature = batch.write_read(0x48, &[0x00], 2);
+//!
+//! let responses = i2c.execute(&batch)?;
+//! println!("temperature: {:02x?}", &responses[temperature]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`I2c`]: struct.I2c.html
+//! [`Batch`]: struct.Batch.html
+
+use std::{error, fmt};
+
+use crate::bitmode::Mpsse;
+use crate::mpsse::{BitOrder, Commands, Edge, Response, Responses};
+use crate::prop::MpsseSupport;
+use crate::{Error, ErrorKind, Port, Result};
+
+const PIN_SCL: u8 = 0x01;
+const PIN_SDA: u8 = 0x02;
+/// ADBUS0-ADBUS2 are open-drain on the FT232H.
+const OPEN_DRAIN_PINS: u8 = 0x07;
+
+/// Number of times each GPIO command of a start or stop condition is repeated, to meet the
+/// I²C setup and hold times.
+const HOLD_REPEAT: usize = 4;
+
+/// An I²C device address.
+///
+/// 7-bit addresses can also be passed as a plain `u8`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Address {
+    /// A 7-bit address (`0x00`-`0x7F`).
+    Seven(u8),
+    /// A 10-bit address (`0x000`-`0x3FF`).
+    Ten(u16),
+}
+
+impl From<u8> for Address {
+    fn from(address: u8) -> Self {
+        Address::Seven(address)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Seven(address) => write!(f, "0x{:02x}", address),
+            Address::Ten(address) => write!(f, "0x{:03x} (10-bit)", address),
+        }
+    }
+}
+
+/// The error returned when an I²C device does not acknowledge a Byte.
+///
+/// This is the `source` of errors of kind `Nack`, and can be obtained by downcasting.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Nack {
+    /// No device acknowledged the address.
+    Address(Address),
+    /// The device did not acknowledge the data Byte at `index` of a write.
+    Data { index: usize },
+}
+
+impl fmt::Display for Nack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Nack::Address(address) => write!(f, "no device responded at address {}", address),
+            Nack::Data { index } => write!(f, "data Byte {} was not acknowledged", index),
+        }
+    }
+}
+
+impl error::Error for Nack {}
+
+/// An I²C master.
+///
+/// Created from a port in MPSSE mode with [`I2c::new`].
+///
+/// [`I2c::new`]: #method.new
+#[derive(Debug)]
+pub struct I2c {
+    port: Port<Mpsse>,
+    /// Whether the port has open-drain outputs.
+    open_drain: bool,
+}
+
+impl I2c {
+    /// Creates an I²C master using `port`, and releases the bus.
+    ///
+    /// The clock frequency is left unchanged, use [`set_clock`] to configure it. Returns an error
+    /// of kind `UnsupportedDevice` if the port does not support 3-phase clocking.
+    ///
+    /// [`set_clock`]: #method.set_clock
+    pub fn new(mut port: Port<Mpsse>) -> Result<Self> {
+        port.set_three_phase_clocking(true)?;
+
+        let open_drain = matches!(port.mpsse_support(), MpsseSupport::FT232H);
+        let mut commands = Commands::new();
+        commands.loopback(false).adaptive_clocking(false);
+        if open_drain {
+            commands.drive_zero(OPEN_DRAIN_PINS, 0);
+        }
+        commands.set_gpio_low(PIN_SCL | PIN_SDA, PIN_SCL);
+        port.execute(&commands)?;
+
+        Ok(Self { port, open_drain })
+    }
+
+    /// Sets the SCL frequency, returning the frequency actually achieved.
+    ///
+    /// Like [`Port::set_clock`], this picks the highest frequency that does not exceed `hz`,
+    /// taking 3-phase clocking into account. Standard I²C frequencies are 100 kHz and 400 kHz.
+    ///
+    /// [`Port::set_clock`]: ../struct.Port.html#method.set_clock
+    pub fn set_clock(&mut self, hz: u32) -> Result<u32> {
+        assert!(hz != 0, "clock frequency must not be 0");
+
+        // 3-phase clocking takes 3 instead of 2 phases per clock cycle.
+        let actual = self.port.set_clock(hz.saturating_mul(3) / 2)?;
+        Ok(actual * 2 / 3)
+    }
+
+    /// Enables or disables clock stretching.
+    ///
+    /// Clock stretching lets devices delay the transfer by holding SCL low. It uses adaptive
+    /// clocking, which requires SCL to be connected to ADBUS7 (GPIOL3).
+    pub fn set_clock_stretching(&mut self, enable: bool) -> Result<()> {
+        self.port.set_adaptive_clocking(enable)
+    }
+
+    /// Creates a new, empty batch of transfers.
+    ///
+    /// The batch can be executed with [`execute`].
+    ///
+    /// [`execute`]: #method.execute
+    pub fn batch(&self) -> Batch {
+        Batch {
+            commands: Commands::new(),
+            acks: Vec::new(),
+            started: false,
+            open_drain: self.open_drain,
+        }
+    }
+
+    /// Executes a batch of transfers in a single USB round trip.
+    ///
+    /// If any Byte of the batch was not acknowledged, an error of kind `Nack` is returned for the
+    /// first such Byte. The rest of the batch is executed regardless.
+    pub fn execute(&mut self, batch: &Batch) -> Result<Responses> {
+        let responses = self.port.execute(&batch.commands)?;
+        for &(ack, nack) in &batch.acks {
+            if responses[ack][0] & 1 != 0 {
+                return Err(Error::new(ErrorKind::Nack, nack));
+            }
+        }
+        Ok(responses)
+    }
+
+    /// Writes `data` to the device at `address`.
+    pub fn write(&mut self, address: impl Into<Address>, data: &[u8]) -> Result<()> {
+        let mut batch = self.batch();
+        batch.write(address, data);
+        self.execute(&batch)?;
+        Ok(())
+    }
+
+    /// Reads from the device at `address` into `buf`.
+    pub fn read(&mut self, address: impl Into<Address>, buf: &mut [u8]) -> Result<()> {
+        let mut batch = self.batch();
+        let response = batch.read(address, buf.len());
+        buf.copy_from_slice(&self.execute(&batch)?[response]);
+        Ok(())
+    }
+
+    /// Writes `data` to the device at `address`, then reads into `buf` after a repeated start.
+    ///
+    /// This is the typical way of reading registers, where `data` is the register address.
+    pub fn write_read(
+        &mut self,
+        address: impl Into<Address>,
+        data: &[u8],
+        buf: &mut [u8],
+    ) -> Result<()> {
+        let mut batch = self.batch();
+        let response = batch.write_read(address, data, buf.len());
+        buf.copy_from_slice(&self.execute(&batch)?[response]);
+        Ok(())
+    }
+
+    /// Returns the underlying port.
+    pub fn into_inner(self) -> Port<Mpsse> {
+        self.port
+    }
+}
+
+/// A batch of I²C transfers, executed in a single USB round trip.
+///
+/// Created by [`I2c::batch`]. Reads return a [`Response`] handle, which is used to look up the
+/// received data in the [`Responses`] returned by [`I2c::execute`].
+///
+/// Besides complete transfers, the batch also provides the individual bus operations, so that
+/// devices with unusual protocols can be accessed.
+///
+/// [`I2c::batch`]: struct.I2c.html#method.batch
+/// [`I2c::execute`]: struct.I2c.html#method.execute
+/// [`Response`]: ../mpsse/struct.Response.html
+/// [`Responses`]: ../mpsse/struct.Responses.html
+#[derive(Debug, Clone)]
+pub struct Batch {
+    commands: Commands,
+    /// The acknowledge bit of each written Byte.
+    acks: Vec<(Response, Nack)>,
+    /// Whether a start condition was sent without a matching stop condition.
+    started: bool,
+    /// Whether SDA is an open-drain output that can be driven high safely.
+    open_drain: bool,
+}
+
+impl Batch {
+    /// Sets SCL and SDA. A high SDA is released instead of driven, emulating an open-drain output.
+    fn set_lines(&mut self, scl: bool, sda: bool) {
+        let mut value = 0;
+        let mut direction = PIN_SCL;
+        if scl {
+            value |= PIN_SCL;
+        }
+        if sda {
+            value |= PIN_SDA;
+        } else {
+            direction |= PIN_SDA;
+        }
+
+        for _ in 0..HOLD_REPEAT {
+            self.commands.set_gpio_low(value, direction);
+        }
+    }
+
+    /// Sends a start condition.
+    ///
+    /// If a transfer is already in progress, this sends a repeated start condition.
+    pub fn start(&mut self) -> &mut Self {
+        if self.started {
+            self.set_lines(false, true);
+        }
+        self.set_lines(true, true);
+        self.set_lines(true, false);
+        self.set_lines(false, false);
+        self.started = true;
+        self
+    }
+
+    /// Sends a stop condition, releasing the bus.
+    pub fn stop(&mut self) -> &mut Self {
+        self.set_lines(false, false);
+        self.set_lines(true, false);
+        self.set_lines(true, true);
+        self.started = false;
+        self
+    }
+
+    /// Writes a Byte and reads the acknowledge bit.
+    fn write_byte(&mut self, byte: u8, nack: Nack) {
+        if self.open_drain {
+            self.commands.set_gpio_low(0, PIN_SCL | PIN_SDA);
+            self.commands
+                .write_bytes(Edge::Falling, BitOrder::MsbFirst, &[byte]);
+        } else {
+            // Only drive SDA for 0 bits, and release it for 1 bits.
+            for bit in (0..8).rev() {
+                let sda = if byte & 1 << bit == 0 { PIN_SDA } else { 0 };
+                self.commands.set_gpio_low(0, PIN_SCL | sda);
+                self.commands
+                    .write_bits(Edge::Falling, BitOrder::MsbFirst, 0x00, 1);
+            }
+        }
+        self.commands.set_gpio_low(0, PIN_SCL);
+        let ack = self.commands.read_bits(Edge::Rising, BitOrder::MsbFirst, 1);
+        self.acks.push((ack, nack));
+    }
+
+    /// Sends the address of the device to access, and whether it is read or written.
+    ///
+    /// This has to follow a (repeated) start condition. For reads from 10-bit addresses, this
+    /// sends the required repeated start condition itself.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the address is out of range.
+    pub fn address(&mut self, address: impl Into<Address>, read: bool) -> &mut Self {
+        let address = address.into();
+        let nack = Nack::Address(address);
+        match address {
+            Address::Seven(addr) => {
+                assert!(addr <= 0x7F, "7-bit address out of range: {:#x}", addr);
+                self.write_byte(addr << 1 | read as u8, nack);
+            }
+            Address::Ten(addr) => {
+                assert!(addr <= 0x3FF, "10-bit address out of range: {:#x}", addr);
+                let high = 0xF0 | (addr >> 7) as u8 & 0x06;
+                self.write_byte(high, nack);
+                self.write_byte(addr as u8, nack);
+                if read {
+                    self.start();
+                    self.write_byte(high | 1, nack);
+                }
+            }
+        }
+        self
+    }
+
+    /// Writes `data` to the addressed device.
+    pub fn write_data(&mut self, data: &[u8]) -> &mut Self {
+        for (index, &byte) in data.iter().enumerate() {
+            self.write_byte(byte, Nack::Data { index });
+        }
+        self
+    }
+
+    /// Reads `len` Bytes from the addressed device.
+    ///
+    /// Every Byte but the last is acknowledged, telling the device to continue sending.
+    pub fn read_data(&mut self, len: usize) -> Response {
+        let mut response = self.commands.raw(&[], 0);
+        for i in 0..len {
+            self.commands.set_gpio_low(0, PIN_SCL);
+            let byte = self
+                .commands
+                .read_bytes(Edge::Rising, BitOrder::MsbFirst, 1);
+            response = if i == 0 { byte } else { response.through(byte) };
+
+            if i + 1 < len {
+                self.commands.set_gpio_low(0, PIN_SCL | PIN_SDA);
+                self.commands
+                    .write_bits(Edge::Falling, BitOrder::MsbFirst, 0x00, 1);
+            } else {
+                // SDA is released, so this sends a NACK.
+                self.commands
+                    .write_bits(Edge::Falling, BitOrder::MsbFirst, 0xFF, 1);
+            }
+        }
+        self.commands.set_gpio_low(0, PIN_SCL);
+        response
+    }
+
+    /// Returns the underlying MPSSE commands, to queue commands without a dedicated method.
+    ///
+    /// ADBUS0-ADBUS2, which are used for the I²C bus, should not be modified this way.
+    pub fn commands(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+
+    /// Queues a complete write of `data` to the device at `address`.
+    pub fn write(&mut self, address: impl Into<Address>, data: &[u8]) -> &mut Self {
+        self.start().address(address, false).write_data(data).stop()
+    }
+
+    /// Queues a complete read of `len` Bytes from the device at `address`.
+    pub fn read(&mut self, address: impl Into<Address>, len: usize) -> Response {
+        self.start().address(address, true);
+        let response = self.read_data(len);
+        self.stop();
+        response
+    }
+
+    /// Queues a write of `data` to the device at `address`, followed by a repeated start and a
+    /// read of `len` Bytes.
+    pub fn write_read(&mut self, address: impl Into<Address>, data: &[u8], len: usize) -> Response {
+        let address = address.into();
+        self.start().address(address, false).write_data(data);
+        self.start().address(address, true);
+        let response = self.read_data(len);
+        self.stop();
+        response
+    }
+}
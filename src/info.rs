@@ -0,0 +1,263 @@
+//! Device enumeration without opening devices.
+
+use std::{fmt, ptr};
+
+use crate::{Error, Ftdi, Result, PIDS_FTDI, VID_FTDI};
+
+/// Information about a connected device, obtained without opening it.
+///
+/// Listing devices through [`list_devices`] only reads descriptors cached by the operating
+/// system, so it works without access permissions to the devices and does not disturb devices in
+/// use by another process. Use [`open`] to start talking to a device.
+///
+/// [`list_devices`]: fn.list_devices.html
+/// [`open`]: #method.open
+#[derive(Debug)]
+pub struct DeviceInfo {
+    device: rusb::Device<rusb::GlobalContext>,
+    vid: u16,
+    pid: u16,
+    device_version: u16,
+    num_interfaces: Option<u8>,
+    port_path: Option<String>,
+    manufacturer: Option<String>,
+    product: Option<String>,
+    serial: Option<String>,
+}
+
+impl DeviceInfo {
+    pub(crate) fn new(
+        device: rusb::Device<rusb::GlobalContext>,
+        descr: &rusb::DeviceDescriptor,
+    ) -> Self {
+        let num_interfaces = match device.config_descriptor(0) {
+            Ok(config) => Some(config.num_interfaces()),
+            Err(e) => {
+                log::debug!("failed to read config descriptor of {:?}: {}", device, e);
+                None
+            }
+        };
+        let port_path = crate::selector::port_path(&device);
+        let string = |name| {
+            port_path
+                .as_deref()
+                .and_then(|path| cached_string(path, name))
+        };
+
+        Self {
+            vid: descr.vendor_id(),
+            pid: descr.product_id(),
+            device_version: crate::backend::libusb::bcd(descr.device_version()),
+            num_interfaces,
+            manufacturer: string("manufacturer"),
+            product: string("product"),
+            serial: string("serial"),
+            port_path,
+            device,
+        }
+    }
+
+    /// Opens the device.
+    pub fn open(&self) -> Result<Ftdi> {
+        Ftdi::open(&self.device)
+    }
+
+    /// Returns the USB Vendor ID of the device.
+    pub fn vid(&self) -> u16 {
+        self.vid
+    }
+
+    /// Returns the USB Product ID of the device.
+    pub fn pid(&self) -> u16 {
+        self.pid
+    }
+
+    /// Returns the USB bus number the device is attached to.
+    pub fn bus_number(&self) -> u8 {
+        self.device.bus_number()
+    }
+
+    /// Returns the USB address assigned to the device on its bus.
+    pub fn device_address(&self) -> u8 {
+        self.device.address()
+    }
+
+    /// Returns the physical port path of the device (eg. `1-4.2`).
+    ///
+    /// See [`DeviceSelector::port_path`] for the format.
+    ///
+    /// [`DeviceSelector::port_path`]: struct.DeviceSelector.html#method.port_path
+    pub fn port_path(&self) -> Option<&str> {
+        self.port_path.as_deref()
+    }
+
+    /// Returns the `bcdDevice` release number reported by the device.
+    pub fn device_version(&self) -> u16 {
+        self.device_version
+    }
+
+    /// Returns the FTDI model identification, or `None` if the device version and interface count
+    /// do not belong to a known FTDI chip.
+    pub fn model(&self) -> Option<&'static str> {
+        detect_model(self.device_version, self.num_interfaces)
+    }
+
+    /// Returns the number of interfaces of the device, which corresponds to the number of ports
+    /// of FTDI devices.
+    pub fn num_interfaces(&self) -> Option<u8> {
+        self.num_interfaces
+    }
+
+    /// Returns the manufacturer string, if the operating system has it cached.
+    ///
+    /// Currently, cached strings are only available on Linux. On other systems, open the device to
+    /// read its strings.
+    pub fn manufacturer(&self) -> Option<&str> {
+        self.manufacturer.as_deref()
+    }
+
+    /// Returns the product description string, if the operating system has it cached.
+    pub fn product(&self) -> Option<&str> {
+        self.product.as_deref()
+    }
+
+    /// Returns the serial number string, if the operating system has it cached.
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+}
+
+impl Clone for DeviceInfo {
+    fn clone(&self) -> Self {
+        // rusb's `Device` does not implement `Clone`, so take another reference manually.
+        // Safety: `self.device` is a valid device, and `from_libusb` increments its refcount.
+        let device = unsafe {
+            rusb::Device::from_libusb(
+                rusb::GlobalContext::default(),
+                ptr::NonNull::new_unchecked(self.device.as_raw()),
+            )
+        };
+        Self {
+            device,
+            vid: self.vid,
+            pid: self.pid,
+            device_version: self.device_version,
+            num_interfaces: self.num_interfaces,
+            port_path: self.port_path.clone(),
+            manufacturer: self.manufacturer.clone(),
+            product: self.product.clone(),
+            serial: self.serial.clone(),
+        }
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Bus {:03} Address {:03}: ID {:04x}:{:04x} {}",
+            self.bus_number(),
+            self.device_address(),
+            self.vid,
+            self.pid,
+            self.model().unwrap_or("unknown model"),
+        )?;
+        if let Some(product) = &self.product {
+            write!(f, " ({:?})", product)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists all FTDI devices on the system, without opening them.
+///
+/// This returns all devices whose VID and PID match known FTDI products.
+pub fn list_devices() -> Result<Vec<DeviceInfo>> {
+    list_filtered(|vid, pid| vid == VID_FTDI && PIDS_FTDI.contains(&pid))
+}
+
+/// Lists all devices matching the given IDs, without opening them.
+pub fn list_devices_by_id(vid: u16, pid: u16) -> Result<Vec<DeviceInfo>> {
+    list_filtered(|v, p| v == vid && p == pid)
+}
+
+fn list_filtered(filter: impl Fn(u16, u16) -> bool) -> Result<Vec<DeviceInfo>> {
+    let list = rusb::devices().map_err(Error::usb)?;
+    let mut infos = Vec::new();
+    for device in list.iter() {
+        let descr = match device.device_descriptor() {
+            Ok(descr) => descr,
+            Err(e) => {
+                log::debug!("failed to read device descriptor of {:?}: {}", device, e);
+                continue;
+            }
+        };
+        if filter(descr.vendor_id(), descr.product_id()) {
+            infos.push(DeviceInfo::new(device, &descr));
+        }
+    }
+
+    Ok(infos)
+}
+
+/// Identifies the FTDI model from its `bcdDevice` release number and, if known, the number of
+/// interfaces it exposes.
+///
+/// A device whose interface count does not match the chip its release number identifies would be
+/// rejected by [`Ftdi::open`], so it is not reported as that model either.
+///
+/// [`Ftdi::open`]: struct.Ftdi.html#method.open
+fn detect_model(device_version: u16, num_interfaces: Option<u8>) -> Option<&'static str> {
+    let props = crate::prop::lookup(device_version)?;
+    match num_interfaces {
+        Some(n) if usize::from(n) != props.ports.len() => None,
+        _ => Some(props.model),
+    }
+}
+
+/// Reads a string descriptor the OS has cached for the device at `port_path`.
+#[cfg(target_os = "linux")]
+fn cached_string(port_path: &str, name: &str) -> Option<String> {
+    let path = format!("/sys/bus/usb/devices/{}/{}", port_path, name);
+    let s = std::fs::read_to_string(path).ok()?;
+    Some(s.trim_end_matches('\n').to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cached_string(_port_path: &str, _name: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::detect_model;
+
+    #[test]
+    fn model_detection() {
+        assert_eq!(detect_model(0x0200, Some(1)), Some("FT232AM"));
+        assert_eq!(detect_model(0x0400, Some(1)), Some("FT232BM"));
+        assert_eq!(detect_model(0x0500, Some(2)), Some("FT2232C/D"));
+        assert_eq!(detect_model(0x0600, Some(1)), Some("FT232R"));
+        assert_eq!(detect_model(0x0700, Some(2)), Some("FT2232H"));
+        assert_eq!(detect_model(0x0800, Some(4)), Some("FT4232H"));
+        assert_eq!(detect_model(0x0900, Some(1)), Some("FT232H"));
+        assert_eq!(detect_model(0x1000, Some(1)), Some("FT-X"));
+
+        // Interface count unavailable.
+        assert_eq!(detect_model(0x0700, None), Some("FT2232H"));
+    }
+
+    #[test]
+    fn unknown_models() {
+        // Interface count does not match the chip.
+        assert_eq!(detect_model(0x0600, Some(2)), None);
+        assert_eq!(detect_model(0x0700, Some(1)), None);
+        assert_eq!(detect_model(0x0900, Some(4)), None);
+
+        // Unassigned or non-FTDI release numbers.
+        assert_eq!(detect_model(0x0000, Some(1)), None);
+        assert_eq!(detect_model(0x0300, Some(1)), None);
+        assert_eq!(detect_model(0x0601, Some(1)), None);
+        assert_eq!(detect_model(0x1100, Some(1)), None);
+    }
+}
@@ -5,23 +5,49 @@
 #![doc(test(attr(deny(unused_imports, unused_must_use))))]
 #![warn(missing_debug_implementations, rust_2018_idioms)]
 
+#[cfg(feature = "async")]
+mod async_port;
+pub mod backend;
 pub mod bitmode;
+pub mod eeprom;
 mod error;
+#[cfg(feature = "rusb")]
+mod hotplug;
+pub mod i2c;
+#[cfg(feature = "rusb")]
+mod info;
+pub mod mpsse;
 mod port;
 mod prop;
 mod readme;
+#[cfg(feature = "rusb")]
+mod selector;
 mod serial;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod spi;
 
-use std::cell::{RefCell, RefMut};
 use std::fmt;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+#[cfg(feature = "rusb")]
+use backend::libusb::LibusbBackend;
+use backend::{Backend, DeviceDescriptor};
+use eeprom::{EepromBackup, EepromConfig, EepromInfo, EepromType};
 use prop::DeviceProps;
 
+#[cfg(feature = "async")]
+pub use async_port::AsyncPort;
 pub use error::{Error, ErrorKind};
+#[cfg(feature = "rusb")]
+pub use hotplug::{HotplugEvent, HotplugWatcher};
+#[cfg(feature = "rusb")]
+pub use info::{list_devices, list_devices_by_id, DeviceInfo};
 pub use port::Port;
-pub use serial::{FlowControl, ModemStatus, Parity, StopBits};
+#[cfg(feature = "rusb")]
+pub use selector::DeviceSelector;
+pub use serial::{DataBits, FlowControl, ModemStatus, Parity, SerialConfig, StopBits};
 
 /// A result type with the error hardwired to [`Error`].
 ///
@@ -34,8 +60,8 @@ pub const VID_FTDI: u16 = 0x0403;
 /// Product IDs used by FTDI's official devices.
 pub const PIDS_FTDI: &[u16] = &[0x6001, 0x6010, 0x6011, 0x6015];
 
-/// USB device type providing shared access from multiple ports.
-type UsbHandle = Rc<RefCell<rusb::DeviceHandle<rusb::GlobalContext>>>;
+/// USB device type providing shared access from multiple ports (and threads).
+type UsbHandle = Arc<dyn Backend>;
 
 #[allow(unused)]
 #[repr(u8)]
@@ -69,14 +95,18 @@ const REQ_WRITE: u8 = REQ_TYPE_VENDOR | REQ_RECIPIENT_DEVICE | REQ_DIR_OUT;
 pub struct Ftdi {
     device: UsbHandle,
     timeout: Duration,
-    port_eps: [(u8, u8); 4],
+    /// `(ep_in, ep_out, max_packet_size)` for each port.
+    port_eps: [(u8, u8, u16); 4],
     properties: &'static DeviceProps,
+    /// Cached result of EEPROM detection.
+    eeprom_info: Mutex<Option<EepromInfo>>,
 }
 
 impl Ftdi {
     const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
 
     /// Opens the only FTDI device connected to the system.
+    #[cfg(feature = "rusb")]
     pub fn open_unique() -> Result<Self> {
         Self::open_filtered(|dev| {
             let descr = dev.device_descriptor().map_err(Error::usb)?;
@@ -87,6 +117,7 @@ impl Ftdi {
     /// Opens an FTDI device with the given VID and PID.
     ///
     /// If multiple devices match the IDs, an error will be returned.
+    #[cfg(feature = "rusb")]
     pub fn open_by_id(vid: u16, pid: u16) -> Result<Self> {
         Self::open_filtered(|dev| {
             let descr = dev.device_descriptor().map_err(Error::usb)?;
@@ -98,6 +129,7 @@ impl Ftdi {
     ///
     /// This address in unique per system, so only a single USB device can match. The address is not
     /// device-specific and may change when the device is replugged.
+    #[cfg(feature = "rusb")]
     pub fn open_by_addr(bus_number: u8, device_address: u8) -> Result<Self> {
         Self::open_filtered(|dev| {
             Ok(dev.bus_number() == bus_number && dev.address() == device_address)
@@ -107,166 +139,138 @@ impl Ftdi {
     /// Opens a unique device that matches a `filter` predicate.
     ///
     /// Private, since we don't want to make rusb a public dependency.
+    #[cfg(feature = "rusb")]
     fn open_filtered(
         mut filter: impl FnMut(&rusb::Device<rusb::GlobalContext>) -> Result<bool>,
     ) -> Result<Self> {
         let list = rusb::devices().map_err(Error::usb)?;
-        let mut selected_device = None;
+        let mut selected_devices = Vec::new();
         for device in list.iter() {
+            // Run the filter on every device, so that it can collect statistics.
             if filter(&device)? {
-                if selected_device.is_some() {
-                    return Err(Error::from_kind(ErrorKind::MultipleDevicesFound));
-                }
-                selected_device = Some(device);
+                selected_devices.push(device);
             }
         }
 
-        match selected_device {
-            Some(device) => Self::open(device),
-            None => Err(Error::from_kind(ErrorKind::NoDeviceFound)),
+        match selected_devices.len() {
+            0 => Err(Error::from_kind(ErrorKind::NoDeviceFound)),
+            1 => Self::open(&selected_devices[0]),
+            _ => Err(Error::from_kind(ErrorKind::MultipleDevicesFound)),
         }
     }
 
-    fn open(device: rusb::Device<rusb::GlobalContext>) -> Result<Self> {
+    #[cfg(feature = "rusb")]
+    fn open(device: &rusb::Device<rusb::GlobalContext>) -> Result<Self> {
         log::debug!("Ftdi::open(device = {:?})", device);
 
-        let descr = device.device_descriptor().map_err(Error::usb)?;
+        Self::from_backend(LibusbBackend::open(device)?)
+    }
+
+    /// Creates an `Ftdi` instance that drives an already opened device through `backend`.
+    ///
+    /// This allows using `rftdi` on top of USB transports other than libusb. The device's
+    /// descriptors will be checked to make sure it is a supported FTDI device.
+    pub fn from_backend(backend: impl Backend + 'static) -> Result<Self> {
+        let descr = backend.device_descriptor();
 
-        if descr.num_configurations() != 1 {
+        if descr.num_configurations != 1 {
             log::error!(
                 "device has {} configurations, expected 1",
-                descr.num_configurations()
+                descr.num_configurations
             );
             return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
         }
 
-        let conf_descr = device.active_config_descriptor().map_err(Error::usb)?;
-
-        // Every interface must have vendor descriptors and a pair of bulk endpoints.
-        let mut port_eps = [(0, 0); 4];
-        for (intf_index, intf) in conf_descr.interfaces().enumerate() {
-            let mut iter = intf.descriptors();
-            let descr = iter.next();
-
-            match descr {
-                Some(descr) => {
-                    if descr.num_endpoints() == 2 {
-                        let (mut ep_in, mut ep_out) = (None, None);
-
-                        for ep in descr.endpoint_descriptors() {
-                            match ep.direction() {
-                                rusb::Direction::In => ep_in = Some(ep.address()),
-                                rusb::Direction::Out => ep_out = Some(ep.address()),
-                            }
-                        }
-
-                        match (ep_in, ep_out) {
-                            (Some(ep_in), Some(ep_out)) => {
-                                port_eps[intf_index] = (ep_in, ep_out);
-                            }
-                            _ => {
-                                log::error!("interface has invalid endpoint configuration");
-                                return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
-                            }
-                        }
-                    } else {
-                        log::error!(
-                            "interface has {} endpoints, expected 2",
-                            descr.num_endpoints()
-                        );
-                        return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
-                    }
-                }
-                None => {
-                    log::error!("missing interface descriptor");
-                    return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
-                }
-            }
-
-            if iter.next().is_some() {
-                log::error!("found extra interface descriptor");
-                return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
-            }
-        }
-
-        let version = descr.device_version();
-        if version.minor() != 0 || version.sub_minor() != 0 {
-            return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
-        }
-
-        let properties = match prop::DEVICES.get(version.major() as usize) {
-            Some(Some(props)) => props,
-            _ => return Err(Error::from_kind(ErrorKind::UnsupportedDevice)),
+        let properties = match prop::lookup(descr.device_version) {
+            Some(props) => props,
+            None => return Err(Error::from_kind(ErrorKind::UnsupportedDevice)),
         };
 
-        if usize::from(conf_descr.num_interfaces()) != properties.ports.len() {
+        let interfaces = backend.interfaces()?;
+        if interfaces.len() != properties.ports.len() {
             log::error!(
                 "device reports {} interfaces, expected {}",
-                conf_descr.num_interfaces(),
+                interfaces.len(),
                 properties.ports.len()
             );
             return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
         }
 
-        let mut device = device.open().map_err(|e| {
-            if cfg!(windows) && matches!(e, rusb::Error::NotSupported | rusb::Error::NotFound) {
-                // Provide a more helpful error message on non-plug-and-play platforms.
-                Error::new(
-                    ErrorKind::Usb,
-                    format!(
-                        "{} (this error may be caused by not having the WinUSB driver installed; \
-                            use Zadig (https://zadig.akeo.ie/) to install it for the FTDI device; \
-                            this will replace any existing driver)",
-                        e
-                    ),
-                )
-            } else {
-                Error::new(ErrorKind::Usb, e)
+        // Every interface must have vendor descriptors and a pair of bulk endpoints.
+        let mut port_eps = [(0, 0, 0); 4];
+        for (intf_index, intf) in interfaces.iter().enumerate() {
+            match intf.num_alt_settings {
+                0 => {
+                    log::error!("missing interface descriptor");
+                    return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
+                }
+                1 => {}
+                _ => {
+                    log::error!("found extra interface descriptor");
+                    return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
+                }
             }
-        })?;
 
-        device.set_auto_detach_kernel_driver(true).ok();
+            if intf.endpoints.len() != 2 {
+                log::error!(
+                    "interface has {} endpoints, expected 2",
+                    intf.endpoints.len()
+                );
+                return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
+            }
+
+            let ep_in = intf.endpoints.iter().find(|ep| ep.is_in());
+            let ep_out = intf.endpoints.iter().find(|ep| !ep.is_in());
+            match (ep_in, ep_out) {
+                (Some(ep_in), Some(ep_out)) => {
+                    port_eps[intf_index] = (ep_in.address, ep_out.address, ep_in.max_packet_size);
+                }
+                _ => {
+                    log::error!("interface has invalid endpoint configuration");
+                    return Err(Error::from_kind(ErrorKind::UnsupportedDevice));
+                }
+            }
+        }
 
         Ok(Self {
-            device: Rc::new(RefCell::new(device)),
+            device: Arc::new(backend),
             properties,
             port_eps,
             timeout: Self::DEFAULT_TIMEOUT,
+            eeprom_info: Mutex::new(None),
         })
     }
 
-    fn dev(&self) -> RefMut<'_, rusb::DeviceHandle<rusb::GlobalContext>> {
-        self.device.borrow_mut()
+    fn dev(&self) -> &dyn Backend {
+        &*self.device
     }
 
-    fn dev_descr(&self) -> rusb::DeviceDescriptor {
-        // This is infallible since libusb 1.0.16, which is from 2013, so we just assume that it
-        // won't fail.
-        self.dev().device().device_descriptor().unwrap()
+    fn dev_descr(&self) -> DeviceDescriptor {
+        self.dev().device_descriptor()
     }
 
     /// Returns the USB Product ID of this device.
     pub fn pid(&self) -> u16 {
-        self.dev_descr().product_id()
+        self.dev_descr().product_id
     }
 
     /// Returns the USB Vendor ID of this device.
     pub fn vid(&self) -> u16 {
-        self.dev_descr().vendor_id()
+        self.dev_descr().vendor_id
     }
 
     /// Returns the USB bus number this device is attached to.
     ///
     /// Alongside `device_address()`, this uniquely identifies a device connected to the system.
     pub fn bus_number(&self) -> u8 {
-        self.dev().device().bus_number()
+        self.dev().bus_number()
     }
 
     /// Returns the USB address assigned to the device on its bus.
     ///
     /// Alongside `bus_number()`, this uniquely identifies a device connected to the system.
     pub fn device_address(&self) -> u8 {
-        self.dev().device().address()
+        self.dev().address()
     }
 
     /// Reads the serial number string from the device.
@@ -274,20 +278,19 @@ impl Ftdi {
     /// Most FTDI devices do not have unique serial number strings, so this cannot be used to
     /// identify devices.
     pub fn serial(&self) -> Result<String> {
-        let descr = self.dev_descr();
-        Ok(self
-            .dev()
-            .read_serial_number_string_ascii(&descr)
-            .map_err(Error::usb)?)
+        self.read_string(self.dev_descr().serial_number_string_index)
     }
 
     /// Reads the product description string from the device.
     pub fn product(&self) -> Result<String> {
-        let descr = self.dev_descr();
-        Ok(self
-            .dev()
-            .read_product_string_ascii(&descr)
-            .map_err(Error::usb)?)
+        self.read_string(self.dev_descr().product_string_index)
+    }
+
+    fn read_string(&self, index: Option<u8>) -> Result<String> {
+        match index {
+            Some(index) => self.dev().read_string(index, self.timeout),
+            None => Err(Error::other("device does not have this string descriptor")),
+        }
     }
 
     /// Returns the FTDI model identification.
@@ -300,7 +303,7 @@ impl Ftdi {
 
     /// Resets the USB device.
     pub fn reset_device(&mut self) -> Result<()> {
-        Ok(self.dev().reset().map_err(Error::usb)?)
+        self.dev().reset()
     }
 
     /// Returns the configured timeout for USB operations.
@@ -316,20 +319,19 @@ impl Ftdi {
     /// Reads a 16-bit word from the EEPROM.
     ///
     /// The caller has to ensure that the word address is in bounds, or the returned value is
-    /// meaningless.
+    /// meaningless. [`read_eeprom_words`] checks this automatically.
+    ///
+    /// [`read_eeprom_words`]: #method.read_eeprom_words
     pub fn read_eeprom_word(&self, word_addr: u16) -> Result<u16> {
         let mut buf = [0; 2];
-        let n = self
-            .dev()
-            .read_control(
-                REQ_READ,
-                ControlReq::ReadEeprom as u8,
-                0,
-                word_addr,
-                &mut buf,
-                self.timeout,
-            )
-            .map_err(Error::usb)?;
+        let n = self.dev().read_control(
+            REQ_READ,
+            ControlReq::ReadEeprom as u8,
+            0,
+            word_addr,
+            &mut buf,
+            self.timeout,
+        )?;
         assert_eq!(n, 2);
         Ok(u16::from_le_bytes(buf))
     }
@@ -337,22 +339,25 @@ impl Ftdi {
     /// Writes a 16-bit word to the EEPROM.
     ///
     /// **Warning**: This can overwrite the device configuration, which can brick the device. Use
-    /// with caution!
+    /// with caution! Changing the configuration area also requires updating the checksum, or the
+    /// device will ignore the EEPROM. Prefer using [`write_eeprom_config`], which takes care of
+    /// that.
     ///
     /// The caller has to ensure that the word address is in bounds, or this operation might
     /// misbehave (eg. by writing to unintended EEPROM locations, or by not writing data at all).
+    /// [`write_eeprom_words`] checks this automatically.
+    ///
+    /// [`write_eeprom_words`]: #method.write_eeprom_words
+    /// [`write_eeprom_config`]: #method.write_eeprom_config
     pub fn write_eeprom_word(&self, word_addr: u16, word: u16) -> Result<()> {
-        let n = self
-            .dev()
-            .write_control(
-                REQ_WRITE,
-                ControlReq::WriteEeprom as u8,
-                word,
-                word_addr,
-                &mut [],
-                self.timeout,
-            )
-            .map_err(Error::usb)?;
+        let n = self.dev().write_control(
+            REQ_WRITE,
+            ControlReq::WriteEeprom as u8,
+            word,
+            word_addr,
+            &[],
+            self.timeout,
+        )?;
         assert_eq!(n, 0);
         Ok(())
     }
@@ -365,21 +370,313 @@ impl Ftdi {
     /// This will not use the USB timeout configured with `set_timeout`. Instead, the `timeout`
     /// parameter will be used, since EEPROM erasure may take longer than other operations.
     pub fn erase_eeprom(&self, timeout: Duration) -> Result<()> {
-        let n = self
-            .dev()
-            .write_control(
-                REQ_WRITE,
-                ControlReq::EraseEeprom as u8,
-                0,
-                0,
-                &mut [],
-                timeout,
-            )
-            .map_err(Error::usb)?;
+        let n = self.dev().write_control(
+            REQ_WRITE,
+            ControlReq::EraseEeprom as u8,
+            0,
+            0,
+            &[],
+            timeout,
+        )?;
         assert_eq!(n, 0);
+        *self.eeprom_info.lock().unwrap() = None;
         Ok(())
     }
 
+    /// Detects the type and size of the EEPROM.
+    ///
+    /// The FT232R and FT-X always use their internal memory. For other chips, the size of the
+    /// external EEPROM is detected by reading it until its contents wrap around. This does not
+    /// work for blank EEPROMs, which are reported as [`EepromType::Absent`].
+    ///
+    /// The result is cached until the EEPROM is erased.
+    ///
+    /// [`EepromType::Absent`]: eeprom/enum.EepromType.html#variant.Absent
+    pub fn eeprom_info(&self) -> Result<EepromInfo> {
+        if let Some(info) = EepromInfo::internal(self.properties.chip) {
+            return Ok(info);
+        }
+
+        let mut cached = self.eeprom_info.lock().unwrap();
+        if let Some(info) = *cached {
+            return Ok(info);
+        }
+
+        let words = (0..256)
+            .map(|addr| self.read_eeprom_word(addr))
+            .collect::<Result<Vec<_>>>()?;
+        let info = EepromInfo::detect(&words);
+        if info.ty() != EepromType::Absent {
+            *cached = Some(info);
+        }
+        Ok(info)
+    }
+
+    /// Checks that `len` words starting at `word_addr` are within the EEPROM.
+    fn check_eeprom_range(&self, word_addr: u16, len: usize) -> Result<()> {
+        let info = self.eeprom_info()?;
+        if info.ty() == EepromType::Absent {
+            return Err(Error::new(
+                ErrorKind::Eeprom,
+                "no EEPROM detected (it is either blank or absent)",
+            ));
+        }
+        if usize::from(word_addr) + len > usize::from(info.size_words()) {
+            return Err(Error::new(
+                ErrorKind::Eeprom,
+                format!(
+                    "EEPROM access to 0x{:02x}..0x{:02x} is out of range (EEPROM has {} words)",
+                    word_addr,
+                    usize::from(word_addr) + len,
+                    info.size_words()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads consecutive words from the EEPROM into `buf`, starting at `word_addr`.
+    ///
+    /// Returns an error of kind [`ErrorKind::Eeprom`] if the range is not within the EEPROM
+    /// reported by [`eeprom_info`].
+    ///
+    /// [`ErrorKind::Eeprom`]: enum.ErrorKind.html#variant.Eeprom
+    /// [`eeprom_info`]: #method.eeprom_info
+    pub fn read_eeprom_words(&self, word_addr: u16, buf: &mut [u16]) -> Result<()> {
+        self.check_eeprom_range(word_addr, buf.len())?;
+        for (addr, word) in (word_addr..).zip(buf) {
+            *word = self.read_eeprom_word(addr)?;
+        }
+        Ok(())
+    }
+
+    /// Writes consecutive words to the EEPROM, starting at `word_addr`.
+    ///
+    /// **Warning**: This can overwrite the device configuration, which can brick the device. Use
+    /// with caution! Like [`write_eeprom_word`], this does not update the checksum.
+    ///
+    /// Returns an error of kind [`ErrorKind::Eeprom`] if the range is not within the EEPROM
+    /// reported by [`eeprom_info`].
+    ///
+    /// [`write_eeprom_word`]: #method.write_eeprom_word
+    /// [`ErrorKind::Eeprom`]: enum.ErrorKind.html#variant.Eeprom
+    /// [`eeprom_info`]: #method.eeprom_info
+    pub fn write_eeprom_words(&self, word_addr: u16, words: &[u16]) -> Result<()> {
+        self.check_eeprom_range(word_addr, words.len())?;
+        for (addr, &word) in (word_addr..).zip(words) {
+            self.write_eeprom_word(addr, word)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the whole EEPROM.
+    ///
+    /// The size of the EEPROM is determined by [`eeprom_info`]. If no EEPROM was detected, an
+    /// error of kind [`ErrorKind::Eeprom`] is returned.
+    ///
+    /// [`eeprom_info`]: #method.eeprom_info
+    /// [`ErrorKind::Eeprom`]: enum.ErrorKind.html#variant.Eeprom
+    pub fn read_eeprom_image(&self) -> Result<Vec<u16>> {
+        let mut words = vec![0; usize::from(self.eeprom_info()?.size_words())];
+        self.read_eeprom_words(0, &mut words)?;
+        Ok(words)
+    }
+
+    /// Writes a complete EEPROM image.
+    ///
+    /// **Warning**: This overwrites the device configuration, which can brick the device. Use
+    /// with caution! The image is written as-is, so it needs to have a valid checksum (see
+    /// [`eeprom::update_checksum`]).
+    ///
+    /// `image` must be exactly as large as the EEPROM. Only words that differ from the current
+    /// EEPROM contents are written. Afterwards, the EEPROM is read back and compared against
+    /// `image`, and a mismatch is reported as an error of kind [`ErrorKind::Eeprom`].
+    ///
+    /// The size of a blank EEPROM cannot be detected (see [`eeprom_info`]), so any image of 64, 128
+    /// or 256 words is accepted in that case. After writing it, the EEPROM size is detected again
+    /// and has to match the size of `image`.
+    ///
+    /// [`eeprom::update_checksum`]: eeprom/fn.update_checksum.html
+    /// [`ErrorKind::Eeprom`]: enum.ErrorKind.html#variant.Eeprom
+    /// [`eeprom_info`]: #method.eeprom_info
+    pub fn write_eeprom_image(&self, image: &[u16]) -> Result<()> {
+        let info = self.eeprom_info()?;
+        if info.ty() == EepromType::Absent {
+            return self.write_blank_eeprom(image);
+        }
+
+        let size = info.size_words();
+        if image.len() != usize::from(size) {
+            return Err(Error::new(
+                ErrorKind::Eeprom,
+                format!(
+                    "image has {} words, but the EEPROM has {}",
+                    image.len(),
+                    size
+                ),
+            ));
+        }
+
+        let current = self.read_eeprom_image()?;
+        for (addr, (&old, &new)) in current.iter().zip(image).enumerate() {
+            if old != new {
+                self.write_eeprom_word(addr as u16, new)?;
+            }
+        }
+
+        self.verify_eeprom_image(image)
+    }
+
+    /// Writes `image` to a blank EEPROM, whose size is only known after writing it.
+    fn write_blank_eeprom(&self, image: &[u16]) -> Result<()> {
+        if !eeprom::is_valid_size(image.len()) {
+            return Err(Error::new(
+                ErrorKind::Eeprom,
+                format!(
+                    "image has {} words, which is not the size of a supported EEPROM",
+                    image.len()
+                ),
+            ));
+        }
+
+        // Blank EEPROMs read as all ones.
+        for (addr, &word) in image.iter().enumerate() {
+            if word != 0xFFFF {
+                self.write_eeprom_word(addr as u16, word)?;
+            }
+        }
+
+        let info = self.eeprom_info()?;
+        if info.ty() == EepromType::Absent {
+            return Err(Error::new(
+                ErrorKind::Eeprom,
+                "no EEPROM detected after writing the image (is one attached?)",
+            ));
+        }
+        if image.len() != usize::from(info.size_words()) {
+            return Err(Error::new(
+                ErrorKind::Eeprom,
+                format!(
+                    "image has {} words, but the EEPROM was detected to have {} after writing it",
+                    image.len(),
+                    info.size_words()
+                ),
+            ));
+        }
+        self.verify_eeprom_image(image)
+    }
+
+    /// Reads the EEPROM back and compares it against `image`.
+    fn verify_eeprom_image(&self, image: &[u16]) -> Result<()> {
+        let written = self.read_eeprom_image()?;
+        match written.iter().zip(image).position(|(a, b)| a != b) {
+            Some(addr) => Err(Error::new(
+                ErrorKind::Eeprom,
+                format!(
+                    "verification failed at word 0x{:02x} (wrote 0x{:04x}, read 0x{:04x})",
+                    addr, image[addr], written[addr]
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates a backup of the EEPROM contents.
+    ///
+    /// The backup records the model, EEPROM size and serial number of the device, so that it can
+    /// only be restored onto a compatible device.
+    pub fn backup_eeprom(&self) -> Result<EepromBackup> {
+        let words = self.read_eeprom_image()?;
+        let serial = match self.serial() {
+            Ok(serial) => Some(serial),
+            Err(e) => {
+                log::debug!("failed to read serial number: {}", e);
+                None
+            }
+        };
+        Ok(EepromBackup::new(self.model(), serial, words))
+    }
+
+    /// Restores an EEPROM backup created by [`backup_eeprom`].
+    ///
+    /// **Warning**: This overwrites the device configuration. Use with caution!
+    ///
+    /// The restore is refused with an error of kind [`ErrorKind::Eeprom`] if the backup was made
+    /// from a different model or an EEPROM of a different size. Restoring the backup of a device
+    /// with a different serial number is allowed, so that configurations can be cloned.
+    ///
+    /// Backups can also be restored onto a blank or erased EEPROM, as described in
+    /// [`write_eeprom_image`].
+    ///
+    /// [`backup_eeprom`]: #method.backup_eeprom
+    /// [`write_eeprom_image`]: #method.write_eeprom_image
+    /// [`ErrorKind::Eeprom`]: enum.ErrorKind.html#variant.Eeprom
+    pub fn restore_eeprom(&self, backup: &EepromBackup) -> Result<()> {
+        if backup.model() != self.model() {
+            return Err(Error::new(
+                ErrorKind::Eeprom,
+                format!(
+                    "backup is from a {}, but device is a {}",
+                    backup.model(),
+                    self.model()
+                ),
+            ));
+        }
+        if let (Some(backup_serial), Ok(serial)) = (backup.serial(), self.serial()) {
+            if backup_serial != serial {
+                log::warn!(
+                    "restoring backup of device {} onto device {}",
+                    backup_serial,
+                    serial
+                );
+            }
+        }
+
+        self.write_eeprom_image(backup.words())
+    }
+
+    /// Reads the EEPROM and checks that its checksum is valid.
+    ///
+    /// FTDI chips ignore the EEPROM contents if the checksum is invalid. A mismatch is reported as
+    /// an error of kind [`ErrorKind::Eeprom`].
+    ///
+    /// [`ErrorKind::Eeprom`]: enum.ErrorKind.html#variant.Eeprom
+    pub fn verify_eeprom(&self) -> Result<()> {
+        eeprom::verify_checksum(self.model(), &self.read_eeprom_image()?)
+    }
+
+    /// Reads and decodes the configuration stored in the EEPROM.
+    pub fn read_eeprom_config(&self) -> Result<EepromConfig> {
+        EepromConfig::decode(self.model(), &self.read_eeprom_image()?)
+    }
+
+    /// Writes a configuration to the EEPROM.
+    ///
+    /// **Warning**: This overwrites the device configuration, which can brick the device if the
+    /// configuration is wrong. Use with caution!
+    ///
+    /// The checksum is recomputed before writing, and the written image is verified afterwards.
+    /// The new configuration takes effect after the device is reset or replugged.
+    pub fn write_eeprom_config(&self, config: &EepromConfig) -> Result<()> {
+        let words = config.encode()?;
+        let size = self.eeprom_info()?.size_words();
+        if config.model() != self.model() || words.len() != usize::from(size) {
+            return Err(Error::new(
+                ErrorKind::Eeprom,
+                format!(
+                    "configuration is for a {} with a {}-word EEPROM, but device is a {} with a \
+                     {}-word EEPROM",
+                    config.model(),
+                    words.len(),
+                    self.model(),
+                    size
+                ),
+            ));
+        }
+
+        self.write_eeprom_image(&words)
+    }
+
     /// Returns the number of ports this device has.
     pub fn num_ports(&self) -> u8 {
         self.properties.ports.len() as u8
@@ -403,8 +700,8 @@ impl Ftdi {
             self.num_ports()
         );
 
-        let (ep_in, ep_out) = self.port_eps[usize::from(port)];
-        Port::open(self, port, ep_in, ep_out)
+        let (ep_in, ep_out, max_packet_size) = self.port_eps[usize::from(port)];
+        Port::open(self, port, ep_in, ep_out, max_packet_size)
     }
 }
 
@@ -419,7 +716,11 @@ impl fmt::Debug for Ftdi {
 
 /// Returns an iterator over all FTDI devices on the system.
 ///
-/// This will try to open every device whose VID and PID match known FTDI products.
+/// This will try to open every device whose VID and PID match known FTDI products. To list devices
+/// without opening them, use [`list_devices`].
+///
+/// [`list_devices`]: fn.list_devices.html
+#[cfg(feature = "rusb")]
 pub fn devices() -> Result<impl Iterator<Item = Result<Ftdi>>> {
     devices_filtered(|dev| {
         let descr = dev.device_descriptor().map_err(Error::usb)?;
@@ -428,6 +729,7 @@ pub fn devices() -> Result<impl Iterator<Item = Result<Ftdi>>> {
 }
 
 /// Returns an iterator over all devices matching the given IDs.
+#[cfg(feature = "rusb")]
 pub fn devices_by_id(vid: u16, pid: u16) -> Result<impl Iterator<Item = Result<Ftdi>>> {
     devices_filtered(move |dev| {
         let descr = dev.device_descriptor().map_err(Error::usb)?;
@@ -435,16 +737,17 @@ pub fn devices_by_id(vid: u16, pid: u16) -> Result<impl Iterator<Item = Result<F
     })
 }
 
-fn devices_filtered(
+#[cfg(feature = "rusb")]
+pub(crate) fn devices_filtered(
     mut filter: impl FnMut(&rusb::Device<rusb::GlobalContext>) -> Result<bool>,
-) -> Result<impl Iterator<Item = Result<Ftdi>>> {
+) -> Result<std::vec::IntoIter<Result<Ftdi>>> {
     let list = rusb::devices().map_err(Error::usb)?;
     let mut vec = Vec::new();
     for device in list.iter() {
         match filter(&device) {
             Ok(true) => {
-                let ftdi = Ftdi::open(device);
-                vec.push(ftdi.map_err(Into::into));
+                let ftdi = Ftdi::open(&device);
+                vec.push(ftdi);
             }
             Ok(false) => {}
             Err(e) => {
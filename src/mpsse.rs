@@ -0,0 +1,740 @@
+//! MPSSE (Multi-Protocol Synchronous Serial Engine) commands.
+//!
+//! In MPSSE mode, the device interprets the data sent to it as a stream of commands that clock
+//! data in and out of the serial pins and control the remaining GPIOs. [`Commands`] queues any
+//! number of commands, which are then sent in one go by [`Port::execute`], saving a USB round
+//! trip per command. Commands that read data return a [`Response`] handle, which is used to look
+//! up the received data in the [`Responses`] returned by `execute`.
+//!
+//! ```no_run
+//! use rftdi::bitmode::Mpsse;
+//! use rftdi::mpsse::{BitOrder, Commands, Edge};
+//! use rftdi::Ftdi;
+//!
+//! # fn main() -> rftdi::Result<()> {
+//! let ftdi = Ftdi::open_unique()?;
+//! let mut port = ftdi.open_port(0)?.into_mode::<Mpsse>()?;
+//!
+//! let mut commands = Commands::new();
+//! commands.set_gpio_low(0x08, 0x0B);
+//! let id = commands.transfer_bytes(Edge::Falling, Edge::Rising, BitOrder::MsbFirst, &[0x9F; 4]);
+//! let pins = commands.get_gpio_high();
+//! commands.set_gpio_low(0x00, 0x0B);
+//!
+//! let responses = port.execute(&commands)?;
+//! println!("ID: {:02x?}", &responses[id][1..]);
+//! println!("GPIOs: {:02x}", responses[pins][0]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Commands`]: struct.Commands.html
+//! [`Port::execute`]: ../struct.Port.html#method.execute
+//! [`Response`]: struct.Response.html
+//! [`Responses`]: struct.Responses.html
+
+use std::ops::Index;
+use std::{cmp, error, fmt};
+
+use crate::bitmode::{self, AnyBitMode};
+use crate::prop::MpsseSupport;
+use crate::{Error, ErrorKind, Port, Result};
+
+// Flags of the data shifting opcodes.
+const FLAG_WRITE_FALLING: u8 = 0x01;
+const FLAG_BITS: u8 = 0x02;
+const FLAG_READ_FALLING: u8 = 0x04;
+const FLAG_LSB_FIRST: u8 = 0x08;
+const FLAG_WRITE: u8 = 0x10;
+const FLAG_READ: u8 = 0x20;
+
+const OP_SET_GPIO_LOW: u8 = 0x80;
+const OP_GET_GPIO_LOW: u8 = 0x81;
+const OP_SET_GPIO_HIGH: u8 = 0x82;
+const OP_GET_GPIO_HIGH: u8 = 0x83;
+const OP_LOOPBACK_ON: u8 = 0x84;
+const OP_LOOPBACK_OFF: u8 = 0x85;
+const OP_CLOCK_DIVISOR: u8 = 0x86;
+const OP_SEND_IMMEDIATE: u8 = 0x87;
+const OP_WAIT_IO_HIGH: u8 = 0x88;
+const OP_WAIT_IO_LOW: u8 = 0x89;
+const OP_DIVIDE_BY_5_OFF: u8 = 0x8A;
+const OP_DIVIDE_BY_5_ON: u8 = 0x8B;
+const OP_THREE_PHASE_ON: u8 = 0x8C;
+const OP_THREE_PHASE_OFF: u8 = 0x8D;
+const OP_ADAPTIVE_ON: u8 = 0x96;
+const OP_ADAPTIVE_OFF: u8 = 0x97;
+const OP_DRIVE_ZERO: u8 = 0x9E;
+
+/// Base clock of the MPSSE engine of FT2232C/D chips, and of `-H` chips with the divide-by-5
+/// prescaler enabled.
+const BASE_CLOCK: u32 = 12_000_000;
+/// Base clock of the MPSSE engine of `-H` chips without the prescaler.
+const BASE_CLOCK_H: u32 = 60_000_000;
+
+/// Reply to an invalid command, followed by the opcode.
+const BAD_COMMAND: u8 = 0xFA;
+/// Invalid opcodes used to synchronize with the engine.
+const SYNC_OPCODES: [u8; 2] = [0xAA, 0xAB];
+/// Invalid opcode appended to every batch. Its rejection marks the end of the batch's responses.
+const SENTINEL: u8 = SYNC_OPCODES[1];
+
+/// Maximum number of Bytes transferred by a single data shifting command.
+const MAX_BYTES: usize = 65536;
+/// Maximum number of Bytes read by a single data shifting command.
+///
+/// This fits into the RX buffer of every chip with an MPSSE (the smallest being the 384 Bytes of
+/// the FT2232C/D), so that `Port::execute` can split long reads into batches.
+const MAX_READ_BYTES: usize = 256;
+
+/// The error returned when the MPSSE engine rejects a command.
+///
+/// This is the `source` of errors of kind `BadCommand`, and can be obtained by downcasting:
+///
+/// ```
+/// use rftdi::mpsse::BadCommand;
+/// use std::error::Error as _;
+///
+/// fn rejected_opcode(e: &rftdi::Error) -> Option<u8> {
+///     let source = e.source()?;
+///     source.downcast_ref::<BadCommand>().map(|e| e.opcode())
+/// }
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BadCommand {
+    opcode: u8,
+}
+
+impl BadCommand {
+    /// Returns the opcode of the rejected command.
+    pub fn opcode(&self) -> u8 {
+        self.opcode
+    }
+}
+
+impl fmt::Display for BadCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid opcode 0x{:02x}", self.opcode)
+    }
+}
+
+impl error::Error for BadCommand {}
+
+/// Clock edge on which data is written or sampled.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+/// Order in which the bits of each Byte are shifted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+/// Handle to the data read by a queued command.
+///
+/// Returned by the reading methods of [`Commands`], and used to index the [`Responses`] of the
+/// executed commands.
+///
+/// [`Commands`]: struct.Commands.html
+/// [`Responses`]: struct.Responses.html
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Response {
+    offset: usize,
+    len: usize,
+}
+
+impl Response {
+    /// Returns a response spanning from the start of `self` to the end of `last`.
+    pub(crate) fn through(self, last: Response) -> Response {
+        Response {
+            offset: self.offset,
+            len: last.offset + last.len - self.offset,
+        }
+    }
+
+    /// Returns the number of Bytes this response consists of.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether this response is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// The data read by a list of executed [`Commands`].
+///
+/// The data belonging to a command is obtained by indexing with the [`Response`] returned when it
+/// was queued.
+///
+/// [`Commands`]: struct.Commands.html
+/// [`Response`]: struct.Response.html
+#[derive(Debug, Clone)]
+pub struct Responses {
+    data: Vec<u8>,
+}
+
+impl Responses {
+    /// Returns the data read by the command that returned `response`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `response` belongs to a different list of commands that expects more data.
+    pub fn get(&self, response: Response) -> &[u8] {
+        &self.data[response.offset..response.offset + response.len]
+    }
+
+    /// Returns all received data, in the order the commands were queued in.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Index<Response> for Responses {
+    type Output = [u8];
+
+    fn index(&self, response: Response) -> &[u8] {
+        self.get(response)
+    }
+}
+
+/// A list of MPSSE commands.
+///
+/// Commands are queued by calling the methods of this type, and sent to the device with
+/// [`Port::execute`]. A list of commands can be executed any number of times.
+///
+/// See the [module documentation](index.html) for an example.
+///
+/// [`Port::execute`]: ../struct.Port.html#method.execute
+#[derive(Debug, Clone, Default)]
+pub struct Commands {
+    buf: Vec<u8>,
+    /// Number of response Bytes expected.
+    response_len: usize,
+    /// `(command end, response end)` of every command that reads data.
+    reads: Vec<(usize, usize)>,
+}
+
+impl Commands {
+    /// Creates an empty list of commands.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether no commands have been queued.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Returns the number of Bytes the device will respond with.
+    pub fn response_len(&self) -> usize {
+        self.response_len
+    }
+
+    /// Returns the encoded commands.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Removes all queued commands.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.response_len = 0;
+        self.reads.clear();
+    }
+
+    /// Queues raw command Bytes, which make the device respond with `response_len` Bytes.
+    ///
+    /// This can be used for commands that have no dedicated method.
+    pub fn raw(&mut self, command: &[u8], response_len: usize) -> Response {
+        self.buf.extend_from_slice(command);
+        self.expect(response_len)
+    }
+
+    fn expect(&mut self, len: usize) -> Response {
+        let response = Response {
+            offset: self.response_len,
+            len,
+        };
+        if len != 0 {
+            self.response_len += len;
+            self.reads.push((self.buf.len(), self.response_len));
+        }
+        response
+    }
+
+    fn data_opcode(flags: u8, write_edge: Edge, read_edge: Edge, order: BitOrder) -> u8 {
+        let mut op = flags;
+        if flags & FLAG_WRITE != 0 && write_edge == Edge::Falling {
+            op |= FLAG_WRITE_FALLING;
+        }
+        if flags & FLAG_READ != 0 && read_edge == Edge::Falling {
+            op |= FLAG_READ_FALLING;
+        }
+        if order == BitOrder::LsbFirst {
+            op |= FLAG_LSB_FIRST;
+        }
+        op
+    }
+
+    /// Queues byte-wise data commands, splitting them to the maximum command length.
+    fn data_bytes(&mut self, op: u8, len: usize, data: Option<&[u8]>) -> Response {
+        let offset = self.response_len;
+        let max = if op & FLAG_READ != 0 {
+            MAX_READ_BYTES
+        } else {
+            MAX_BYTES
+        };
+        for start in (0..len).step_by(max) {
+            let n = (len - start).min(max);
+            self.buf.push(op);
+            self.buf.extend_from_slice(&((n - 1) as u16).to_le_bytes());
+            if let Some(data) = data {
+                self.buf.extend_from_slice(&data[start..start + n]);
+            }
+            if op & FLAG_READ != 0 {
+                self.expect(n);
+            }
+        }
+
+        Response {
+            offset,
+            len: if op & FLAG_READ != 0 { len } else { 0 },
+        }
+    }
+
+    fn data_bits(&mut self, op: u8, count: u8, bits: Option<u8>) -> Response {
+        assert!(
+            (1..=8).contains(&count),
+            "bit count must be between 1 and 8, got {}",
+            count
+        );
+
+        self.buf.push(op | FLAG_BITS);
+        self.buf.push(count - 1);
+        if let Some(bits) = bits {
+            self.buf.push(bits);
+        }
+        self.expect(if op & FLAG_READ != 0 { 1 } else { 0 })
+    }
+
+    /// Clocks out `data`, changing the data output on the given clock `edge`.
+    pub fn write_bytes(&mut self, edge: Edge, order: BitOrder, data: &[u8]) -> &mut Self {
+        let op = Self::data_opcode(FLAG_WRITE, edge, edge, order);
+        self.data_bytes(op, data.len(), Some(data));
+        self
+    }
+
+    /// Clocks in `len` Bytes, sampling the data input on the given clock `edge`.
+    pub fn read_bytes(&mut self, edge: Edge, order: BitOrder, len: usize) -> Response {
+        let op = Self::data_opcode(FLAG_READ, edge, edge, order);
+        self.data_bytes(op, len, None)
+    }
+
+    /// Clocks out `data` and simultaneously clocks in the same number of Bytes.
+    pub fn transfer_bytes(
+        &mut self,
+        write_edge: Edge,
+        read_edge: Edge,
+        order: BitOrder,
+        data: &[u8],
+    ) -> Response {
+        let op = Self::data_opcode(FLAG_WRITE | FLAG_READ, write_edge, read_edge, order);
+        self.data_bytes(op, data.len(), Some(data))
+    }
+
+    /// Clocks out the first `count` bits of `bits` (starting at the MSB or LSB, according to
+    /// `order`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is not in range `1..=8`.
+    pub fn write_bits(&mut self, edge: Edge, order: BitOrder, bits: u8, count: u8) -> &mut Self {
+        let op = Self::data_opcode(FLAG_WRITE, edge, edge, order);
+        self.data_bits(op, count, Some(bits));
+        self
+    }
+
+    /// Clocks in `count` bits, which are returned as a single Byte.
+    ///
+    /// The bits are shifted into the Byte in the direction given by `order`: with `MsbFirst`, the
+    /// last bit read ends up in the LSB, with `LsbFirst` it ends up in the MSB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is not in range `1..=8`.
+    pub fn read_bits(&mut self, edge: Edge, order: BitOrder, count: u8) -> Response {
+        let op = Self::data_opcode(FLAG_READ, edge, edge, order);
+        self.data_bits(op, count, None)
+    }
+
+    /// Clocks out `count` bits of `bits` and simultaneously clocks in `count` bits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is not in range `1..=8`.
+    pub fn transfer_bits(
+        &mut self,
+        write_edge: Edge,
+        read_edge: Edge,
+        order: BitOrder,
+        bits: u8,
+        count: u8,
+    ) -> Response {
+        let op = Self::data_opcode(FLAG_WRITE | FLAG_READ, write_edge, read_edge, order);
+        self.data_bits(op, count, Some(bits))
+    }
+
+    /// Sets the levels and directions of the lower 8 pins (ADBUS/BDBUS).
+    ///
+    /// Each set bit in `direction` makes the corresponding pin an output, which is driven to the
+    /// level of the same bit in `value`. The serial clock and data output pins (bit 0 and 1)
+    /// need to be configured as outputs to clock out data.
+    pub fn set_gpio_low(&mut self, value: u8, direction: u8) -> &mut Self {
+        self.buf
+            .extend_from_slice(&[OP_SET_GPIO_LOW, value, direction]);
+        self
+    }
+
+    /// Sets the levels and directions of the upper 8 pins (ACBUS/BCBUS).
+    pub fn set_gpio_high(&mut self, value: u8, direction: u8) -> &mut Self {
+        self.buf
+            .extend_from_slice(&[OP_SET_GPIO_HIGH, value, direction]);
+        self
+    }
+
+    /// Reads the levels of the lower 8 pins.
+    pub fn get_gpio_low(&mut self) -> Response {
+        self.raw(&[OP_GET_GPIO_LOW], 1)
+    }
+
+    /// Reads the levels of the upper 8 pins.
+    pub fn get_gpio_high(&mut self) -> Response {
+        self.raw(&[OP_GET_GPIO_HIGH], 1)
+    }
+
+    /// Enables or disables the internal loopback from the data output to the data input.
+    pub fn loopback(&mut self, enable: bool) -> &mut Self {
+        self.buf.push(if enable {
+            OP_LOOPBACK_ON
+        } else {
+            OP_LOOPBACK_OFF
+        });
+        self
+    }
+
+    /// Sets the divisor applied to the base clock to derive the serial clock.
+    ///
+    /// The resulting clock frequency is `base / ((1 + divisor) * 2)`.
+    pub fn clock_divisor(&mut self, divisor: u16) -> &mut Self {
+        self.buf.push(OP_CLOCK_DIVISOR);
+        self.buf.extend_from_slice(&divisor.to_le_bytes());
+        self
+    }
+
+    /// Enables or disables the divide-by-5 prescaler of the base clock.
+    ///
+    /// This is only supported by `-H` chips, whose base clock is 60 MHz without the prescaler.
+    /// Prefer [`Port::set_clock`], which takes care of this.
+    ///
+    /// [`Port::set_clock`]: ../struct.Port.html#method.set_clock
+    pub fn divide_by_5(&mut self, enable: bool) -> &mut Self {
+        self.buf.push(if enable {
+            OP_DIVIDE_BY_5_ON
+        } else {
+            OP_DIVIDE_BY_5_OFF
+        });
+        self
+    }
+
+    /// Enables or disables 3-phase data clocking, which makes data valid on both clock edges.
+    ///
+    /// This is only supported by `-H` chips.
+    pub fn three_phase_clocking(&mut self, enable: bool) -> &mut Self {
+        self.buf.push(if enable {
+            OP_THREE_PHASE_ON
+        } else {
+            OP_THREE_PHASE_OFF
+        });
+        self
+    }
+
+    /// Enables or disables adaptive clocking, which waits for the clock to be returned on GPIOL3.
+    ///
+    /// This is only supported by `-H` chips.
+    pub fn adaptive_clocking(&mut self, enable: bool) -> &mut Self {
+        self.buf.push(if enable {
+            OP_ADAPTIVE_ON
+        } else {
+            OP_ADAPTIVE_OFF
+        });
+        self
+    }
+
+    /// Makes the selected pins open-drain outputs, which only drive low levels.
+    ///
+    /// Each set bit in `low` and `high` selects a pin of the lower and upper 8 pins. This is only
+    /// supported by the FT232H.
+    pub fn drive_zero(&mut self, low: u8, high: u8) -> &mut Self {
+        self.buf.extend_from_slice(&[OP_DRIVE_ZERO, low, high]);
+        self
+    }
+
+    /// Makes the device send all data read so far to the host immediately.
+    ///
+    /// [`Port::execute`] does this automatically at the end of each batch of commands that reads
+    /// data.
+    ///
+    /// [`Port::execute`]: ../struct.Port.html#method.execute
+    pub fn send_immediate(&mut self) -> &mut Self {
+        self.buf.push(OP_SEND_IMMEDIATE);
+        self
+    }
+
+    /// Stops executing commands until the GPIOL1 pin is at the given level.
+    pub fn wait_on_io(&mut self, high: bool) -> &mut Self {
+        self.buf.push(if high {
+            OP_WAIT_IO_HIGH
+        } else {
+            OP_WAIT_IO_LOW
+        });
+        self
+    }
+}
+
+/// Returns the frequency and divisor that get closest to `hz` without exceeding it.
+fn clock_divisor(base: u32, hz: u32) -> (u32, u16) {
+    // The frequency is `base / ((1 + divisor) * 2)`.
+    let divisor = cmp::min((base / 2).div_ceil(hz) - 1, 0xFFFF);
+    (base / ((1 + divisor) * 2), divisor as u16)
+}
+
+/// Functionality available in MPSSE mode.
+impl Port<bitmode::Mpsse> {
+    /// Sends `commands` to the device and collects their responses.
+    ///
+    /// Commands are sent in batches whose responses fit into the device's buffer, so that the
+    /// device never has to stop executing commands because the host does not read its responses.
+    /// Most command lists fit into a single batch, which costs one USB round trip.
+    ///
+    /// If the MPSSE engine rejects a command, an error of kind `BadCommand` is returned.
+    pub fn execute(&mut self, commands: &Commands) -> Result<Responses> {
+        // Leftover data (eg. from raw writes to the port) would be mistaken for responses.
+        if !self.rx.pending.is_empty() {
+            let extra = self.rx.pending.drain(..).collect::<Vec<_>>();
+            return Err(unexpected_data(&extra, extra.len(), &[]));
+        }
+
+        let limit = usize::from(self.properties().rx_buf);
+        let mut data = vec![0; commands.response_len];
+        let (mut sent, mut received) = (0, 0);
+        // End of the last command whose response still fits into the current batch.
+        let mut split = (0, 0);
+        for &(command_end, response_end) in &commands.reads {
+            if response_end - received > limit && split.0 > sent {
+                self.send_batch(&commands.buf[sent..split.0], &mut data[received..split.1])?;
+                sent = split.0;
+                received = split.1;
+            }
+            split = (command_end, response_end);
+        }
+        self.send_batch(&commands.buf[sent..], &mut data[received..])?;
+
+        Ok(Responses { data })
+    }
+
+    /// Sets the frequency of the serial clock.
+    ///
+    /// Not every frequency can be generated exactly. This configures the highest frequency that
+    /// does not exceed `hz` (or the lowest supported frequency, if `hz` is below that) and returns
+    /// it. On `-H` chips, the divide-by-5 prescaler is enabled when that allows getting closer to
+    /// `hz`. The maximum frequency is 6 MHz on FT2232C/D chips and 30 MHz on `-H` chips.
+    ///
+    /// Note that enabling 3-phase clocking reduces the effective frequency to 2/3 of this value.
+    ///
+    /// # Parameters
+    ///
+    /// * **`hz`**: The desired frequency. Must not be 0, or this method will panic.
+    pub fn set_clock(&mut self, hz: u32) -> Result<u32> {
+        assert!(hz != 0, "clock frequency must not be 0");
+
+        let mut commands = Commands::new();
+        let (actual, divisor) = if self.mpsse_is_h() {
+            let fast = clock_divisor(BASE_CLOCK_H, hz);
+            let slow = clock_divisor(BASE_CLOCK, hz);
+            // Without the prescaler, the divisor might not be large enough for low frequencies.
+            let use_slow = fast.0 > hz || slow.0 > fast.0;
+            commands.divide_by_5(use_slow);
+            if use_slow {
+                slow
+            } else {
+                fast
+            }
+        } else {
+            clock_divisor(BASE_CLOCK, hz)
+        };
+        commands.clock_divisor(divisor);
+        log::debug!(
+            "set_clock: requested {}, achieved {}, divisor {}",
+            hz,
+            actual,
+            divisor
+        );
+
+        self.execute(&commands)?;
+        Ok(actual)
+    }
+
+    /// Enables or disables 3-phase data clocking.
+    ///
+    /// With 3-phase clocking, data is valid on both the rising and falling clock edge, as required
+    /// by I²C. Returns an error of kind `UnsupportedDevice` on chips other than `-H` chips.
+    pub fn set_three_phase_clocking(&mut self, enable: bool) -> Result<()> {
+        self.require_h("3-phase clocking")?;
+        self.execute(Commands::new().three_phase_clocking(enable))?;
+        Ok(())
+    }
+
+    /// Enables or disables adaptive clocking.
+    ///
+    /// With adaptive clocking, the engine waits for the clock to be returned on GPIOL3 before
+    /// continuing, as required by some ARM JTAG targets. Returns an error of kind
+    /// `UnsupportedDevice` on chips other than `-H` chips.
+    pub fn set_adaptive_clocking(&mut self, enable: bool) -> Result<()> {
+        self.require_h("adaptive clocking")?;
+        self.execute(Commands::new().adaptive_clocking(enable))?;
+        Ok(())
+    }
+
+    /// Returns whether the port supports the MPSSE features of `-H` chips.
+    pub(crate) fn mpsse_is_h(&self) -> bool {
+        matches!(self.mpsse_support(), MpsseSupport::H | MpsseSupport::FT232H)
+    }
+
+    fn require_h(&self, feature: &str) -> Result<()> {
+        if self.mpsse_is_h() {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::UnsupportedDevice,
+                format!("{} does not support {}", self.properties().model, feature),
+            ))
+        }
+    }
+
+    /// Sends a batch of commands and reads their response into `response`.
+    ///
+    /// The batch is followed by an invalid opcode, so that the engine's rejection of it marks the
+    /// end of the responses. Rejections of other commands in the batch arrive before that.
+    fn send_batch(&mut self, commands: &[u8], response: &mut [u8]) -> Result<()> {
+        let mut buf = Vec::with_capacity(commands.len() + 2);
+        buf.extend_from_slice(commands);
+        buf.extend_from_slice(&[SENTINEL, OP_SEND_IMMEDIATE]);
+        self.write_all(&buf)?;
+
+        let expected = response.len() + 2;
+        let mut data = Vec::with_capacity(expected);
+        while data.len() < expected || !data.ends_with(&[BAD_COMMAND, SENTINEL]) {
+            if self.rx.pending.is_empty() {
+                if let Err(e) = self.wait_rx() {
+                    // A rejected command can cut the responses short.
+                    return match bad_command(&data, commands) {
+                        Some(err) => Err(err),
+                        None => Err(e),
+                    };
+                }
+            }
+            data.extend(self.rx.pending.pop_front());
+        }
+        data.truncate(data.len() - 2);
+
+        if data.len() != response.len() {
+            return Err(unexpected_data(
+                &data,
+                data.len() - response.len(),
+                commands,
+            ));
+        }
+        response.copy_from_slice(&data);
+        Ok(())
+    }
+}
+
+/// Returns the error for `extra` Bytes received in addition to the expected responses `data`.
+///
+/// The engine replies to invalid commands with `0xFA` followed by the opcode, which shifts the
+/// rest of the response data.
+fn unexpected_data(data: &[u8], extra: usize, commands: &[u8]) -> Error {
+    bad_command(data, commands).unwrap_or_else(|| {
+        Error::other(format!(
+            "received {} unexpected Bytes from the MPSSE engine",
+            extra
+        ))
+    })
+}
+
+/// Looks for the engine's reply to an invalid command in `data`, received for `commands`.
+fn bad_command(data: &[u8], commands: &[u8]) -> Option<Error> {
+    let replies = data.windows(2).filter(|reply| reply[0] == BAD_COMMAND);
+    // Response data may contain `0xFA` too, so prefer replies naming an opcode we sent.
+    let reply = replies
+        .clone()
+        .find(|reply| commands.contains(&reply[1]))
+        .or_else(|| replies.clone().next())?;
+    Some(Error::new(
+        ErrorKind::BadCommand,
+        BadCommand { opcode: reply[1] },
+    ))
+}
+
+impl<M: AnyBitMode> Port<M> {
+    pub(crate) fn mpsse_support(&self) -> &'static MpsseSupport {
+        &self.properties().ports[usize::from(self.index())].mpsse
+    }
+
+    /// Returns an error if this port does not support MPSSE mode.
+    pub(crate) fn check_mpsse_support(&self) -> Result<()> {
+        match self.mpsse_support() {
+            MpsseSupport::No => Err(Error::new(
+                ErrorKind::UnsupportedDevice,
+                format!(
+                    "port {} of the {} does not support MPSSE mode",
+                    self.index(),
+                    self.properties().model
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Synchronizes with the MPSSE engine after entering MPSSE mode.
+    ///
+    /// This sends invalid opcodes and waits for the engine to reject them, discarding any stale
+    /// data received before the rejection.
+    pub(crate) fn sync_mpsse(&mut self) -> Result<()> {
+        self.purge_rx()?;
+
+        for &opcode in &SYNC_OPCODES {
+            self.write_all(&[opcode, OP_SEND_IMMEDIATE])?;
+
+            let mut last = None;
+            loop {
+                self.wait_rx()?;
+                let mut byte = [0];
+                self.rx.take(&mut byte);
+                if last == Some(BAD_COMMAND) && byte[0] == opcode {
+                    break;
+                }
+                last = Some(byte[0]);
+            }
+        }
+
+        log::debug!("synchronized with MPSSE engine");
+        Ok(())
+    }
+}
@@ -4,16 +4,22 @@
 //! and communicating with individual ports/interfaces of a device.
 
 use std::any::type_name;
-use std::cell::RefMut;
+use std::cmp;
+use std::collections::VecDeque;
 use std::fmt;
 use std::marker::PhantomData;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use bitflags::bitflags;
 
+use crate::backend::Backend;
 use crate::bitmode::{self, AnyBitMode, BitMode};
+use crate::eeprom;
 use crate::prop::DeviceProps;
-use crate::{ControlReq, Error, Ftdi, Result, UsbHandle, REQ_READ, REQ_WRITE};
+use crate::{
+    ControlReq, Error, ErrorKind, Ftdi, ModemStatus, Result, SerialConfig, UsbHandle, REQ_READ,
+    REQ_WRITE,
+};
 
 bitflags! {
     pub struct ResetFlags: u16 {
@@ -23,6 +29,56 @@ bitflags! {
     }
 }
 
+/// Received data and status of a port.
+#[derive(Default)]
+pub(crate) struct RxState {
+    /// Received payload data that wasn't returned to the user yet.
+    pub pending: VecDeque<u8>,
+    /// The modem status from the most recently received packet.
+    pub modem_status: ModemStatus,
+    /// Line errors (OE/PE/FE/BI) accumulated since they were last taken.
+    pub line_errors: ModemStatus,
+}
+
+impl RxState {
+    /// Processes the data received in a bulk IN transfer.
+    ///
+    /// The transfer is split into packets of `packet_size` Bytes. The modem status header is
+    /// stripped from each of them and recorded.
+    pub fn ingest(&mut self, transfer: &[u8], packet_size: usize) {
+        for packet in transfer.chunks(packet_size) {
+            if packet.len() < 2 {
+                log::warn!(
+                    "received {}-byte packet without status header",
+                    packet.len()
+                );
+                continue;
+            }
+
+            let status =
+                ModemStatus::from_bits_truncate(u16::from_le_bytes([packet[0], packet[1]]));
+            self.modem_status = status;
+            self.line_errors |= status & ModemStatus::LINE_ERRORS;
+            self.pending.extend(&packet[2..]);
+        }
+    }
+
+    /// Moves pending data into `buf`, returning the number of Bytes copied.
+    pub fn take(&mut self, buf: &mut [u8]) -> usize {
+        let n = cmp::min(buf.len(), self.pending.len());
+        for (dest, byte) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *dest = byte;
+        }
+        n
+    }
+
+    pub fn take_line_errors(&mut self) -> ModemStatus {
+        let errors = self.line_errors;
+        self.line_errors = ModemStatus::empty();
+        errors
+    }
+}
+
 // FIXME: Hack needed since you can't move out of types that impl `Drop`.
 struct ReleaseOnDrop {
     /// Port/Interface index (0-based).
@@ -32,7 +88,7 @@ struct ReleaseOnDrop {
 
 impl Drop for ReleaseOnDrop {
     fn drop(&mut self) {
-        self.device.borrow_mut().release_interface(self.index).ok();
+        self.device.release_interface(self.index).ok();
     }
 }
 
@@ -47,15 +103,26 @@ pub struct Port<M: AnyBitMode = bitmode::Serial> {
     ep_in: u8,
     /// Bulk OUT endpoint address.
     ep_out: u8,
+    /// Max. packet size of the bulk endpoints.
+    max_packet_size: u16,
     properties: &'static DeviceProps,
+    pub(crate) rx: RxState,
+    /// The UART configuration, if a complete one was applied.
+    pub(crate) serial_config: Option<SerialConfig>,
+    /// CBUS bit-bang pins in I/O mode, read from the EEPROM when first needed.
+    cbus_io: Option<u8>,
     _p: PhantomData<M>,
 }
 
 impl Port {
-    pub(crate) fn open(parent: &Ftdi, index: u8, ep_in: u8, ep_out: u8) -> Result<Self> {
-        let mut dev = parent.dev();
-        dev.claim_interface(index).map_err(Error::usb)?;
-        drop(dev);
+    pub(crate) fn open(
+        parent: &Ftdi,
+        index: u8,
+        ep_in: u8,
+        ep_out: u8,
+        max_packet_size: u16,
+    ) -> Result<Self> {
+        parent.dev().claim_interface(index)?;
 
         let mut this = Self {
             device: ReleaseOnDrop {
@@ -65,39 +132,40 @@ impl Port {
             timeout: parent.timeout,
             ep_in,
             ep_out,
+            max_packet_size,
             properties: parent.properties,
+            rx: RxState::default(),
+            serial_config: None,
+            cbus_io: None,
             _p: PhantomData,
         };
 
         this.reset(ResetFlags::PURGE_RX_TX)?;
-        this.set_bitmode(BitMode::Serial)?;
+        this.set_bitmode(BitMode::Serial, 0)?;
 
         Ok(this)
     }
 }
 
 impl<M: AnyBitMode> Port<M> {
-    pub(crate) fn dev(&self) -> RefMut<'_, rusb::DeviceHandle<rusb::GlobalContext>> {
-        self.device.device.borrow_mut()
+    pub(crate) fn dev(&self) -> &dyn Backend {
+        &*self.device.device
     }
 
-    pub(crate) fn read_control<'b>(
+    pub(crate) fn read_control(
         &self,
         request: ControlReq,
         value: u16,
-        buf: &'b mut [u8],
+        buf: &mut [u8],
     ) -> Result<()> {
-        let n = self
-            .dev()
-            .read_control(
-                REQ_READ,
-                request as u8,
-                value,
-                u16::from(self.device.index) + 1, // bInterfaceNumber + 1
-                buf,
-                self.timeout,
-            )
-            .map_err(Error::usb)?;
+        let n = self.dev().read_control(
+            REQ_READ,
+            request as u8,
+            value,
+            self.interface_number(),
+            buf,
+            self.timeout,
+        )?;
         if n != buf.len() {
             return Err(Error::other(format!(
                 "read {} bytes, expected {}",
@@ -109,17 +177,23 @@ impl<M: AnyBitMode> Port<M> {
     }
 
     pub(crate) fn write_control(&self, request: ControlReq, value: u16, buf: &[u8]) -> Result<()> {
-        let n = self
-            .dev()
-            .write_control(
-                REQ_WRITE,
-                request as u8,
-                value,
-                u16::from(self.device.index) + 1, // bInterfaceNumber + 1
-                buf,
-                self.timeout,
-            )
-            .map_err(Error::usb)?;
+        self.write_control_with_index(request, value, self.interface_number(), buf)
+    }
+
+    /// Performs an OUT control transfer with a custom `wIndex`.
+    ///
+    /// Most requests only put the interface number in `wIndex`, but some (eg. `SetBaudrate`) use
+    /// it to transfer additional data.
+    pub(crate) fn write_control_with_index(
+        &self,
+        request: ControlReq,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+    ) -> Result<()> {
+        let n =
+            self.dev()
+                .write_control(REQ_WRITE, request as u8, value, index, buf, self.timeout)?;
         if n != buf.len() {
             return Err(Error::other(format!(
                 "wrote {} bytes, expected {}",
@@ -131,24 +205,154 @@ impl<M: AnyBitMode> Port<M> {
         Ok(())
     }
 
-    fn set_bitmode(&mut self, mode: BitMode) -> Result<()> {
-        self.write_control(ControlReq::SetBitmode, (mode as u16) << 8 | 0x00, &[])?;
+    /// Returns a new reference to the shared USB device.
+    #[cfg(feature = "async")]
+    pub(crate) fn usb_handle(&self) -> UsbHandle {
+        self.device.device.clone()
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn ep_in(&self) -> u8 {
+        self.ep_in
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn max_packet_size(&self) -> u16 {
+        self.max_packet_size
+    }
+
+    pub(crate) fn properties(&self) -> &'static DeviceProps {
+        self.properties
+    }
+
+    /// Returns the interface number to put in `wIndex` of control requests.
+    pub(crate) fn interface_number(&self) -> u16 {
+        u16::from(self.device.index) + 1 // bInterfaceNumber + 1
+    }
+
+    /// Sends a `SetBitmode` request, with `mask` in the lower byte.
+    ///
+    /// The meaning of `mask` depends on the mode. Most modes use it to select output pins.
+    fn set_bitmode(&mut self, mode: BitMode, mask: u8) -> Result<()> {
+        self.write_control(
+            ControlReq::SetBitmode,
+            (mode as u16) << 8 | u16::from(mask),
+            &[],
+        )?;
         Ok(())
     }
 
     /// Switches the port to mode `T`.
     ///
     /// This consumes the port and returns a new instance with mode parameter `T`.
-    pub fn into_mode<T: AnyBitMode>(mut self) -> Result<Port<T>> {
-        self.set_bitmode(T::MODE)?;
-        Ok(Port {
+    ///
+    /// When switching to MPSSE mode, this synchronizes with the MPSSE engine and discards any
+    /// stale received data. An error of kind `UnsupportedDevice` is returned if the port does not
+    /// support MPSSE mode, or CBUS bit-bang mode when switching to `Cbus`.
+    pub fn into_mode<T: AnyBitMode>(self) -> Result<Port<T>> {
+        self.into_mode_with_mask(0)
+    }
+
+    /// Switches the port to asynchronous bit-bang mode.
+    ///
+    /// Each set bit in `direction` configures the corresponding data pin as an output, all other
+    /// pins are inputs. The outputs can then be driven with [`write_pins`] and [`write_pattern`].
+    ///
+    /// [`write_pins`]: #method.write_pins
+    /// [`write_pattern`]: #method.write_pattern
+    pub fn into_bitbang(self, direction: u8) -> Result<Port<bitmode::Bitbang>> {
+        self.into_mode_with_mask(direction)
+    }
+
+    /// Switches the port to synchronous bit-bang mode.
+    ///
+    /// Like in [`into_bitbang`], each set bit in `direction` configures the corresponding data pin
+    /// as an output. Pins are then driven and sampled with [`transfer`]. Data received before the
+    /// switch is discarded, so that it is not mistaken for samples.
+    ///
+    /// [`into_bitbang`]: #method.into_bitbang
+    /// [`transfer`]: #method.transfer
+    pub fn into_sync_bitbang(self, direction: u8) -> Result<Port<bitmode::Syncbb>> {
+        self.into_mode_with_mask(direction)
+    }
+
+    fn into_mode_with_mask<T: AnyBitMode>(mut self, mask: u8) -> Result<Port<T>> {
+        if T::MODE == BitMode::Mpsse {
+            self.check_mpsse_support()?;
+        }
+        if T::MODE == BitMode::Cbus {
+            self.cbus_io_pins()?;
+        }
+        self.set_bitmode(T::MODE, mask)?;
+        let mut port = Port {
             device: self.device,
             timeout: self.timeout,
             properties: self.properties,
             ep_in: self.ep_in,
             ep_out: self.ep_out,
+            max_packet_size: self.max_packet_size,
+            rx: self.rx,
+            serial_config: self.serial_config,
+            cbus_io: self.cbus_io,
             _p: PhantomData,
-        })
+        };
+        if T::MODE == BitMode::Syncbb {
+            // Received data from the previous mode would be mistaken for pin samples.
+            port.purge_rx()?;
+        }
+        if T::MODE == BitMode::Mpsse {
+            port.sync_mpsse()?;
+        }
+        Ok(port)
+    }
+
+    /// Discards all data received so far, both on the device and in `self.rx`.
+    pub(crate) fn purge_rx(&mut self) -> Result<()> {
+        self.reset(ResetFlags::PURGE_RX)?;
+        self.rx.pending.clear();
+        Ok(())
+    }
+
+    /// Returns the CBUS bit-bang pins that are configured for I/O mode.
+    fn cbus_io_pins(&mut self) -> Result<u8> {
+        if let Some(pins) = self.cbus_io {
+            return Ok(pins);
+        }
+
+        let mut image = [0; 32];
+        for (addr, word) in (0..).zip(image.chunks_mut(2)) {
+            let n = self.dev().read_control(
+                REQ_READ,
+                ControlReq::ReadEeprom as u8,
+                0,
+                addr,
+                word,
+                self.timeout,
+            )?;
+            if n != 2 {
+                return Err(Error::other(format!("read {} bytes, expected 2", n)));
+            }
+        }
+
+        let model = self.properties.model;
+        let pins = eeprom::io_mode_pins(model, &image).ok_or_else(|| {
+            Error::new(
+                ErrorKind::UnsupportedDevice,
+                format!("{} does not support CBUS bit-bang mode", model),
+            )
+        })?;
+        self.cbus_io = Some(pins);
+        Ok(pins)
+    }
+
+    /// Returns the configured timeout for USB operations.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Sets the timeout to use for USB operations.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
     }
 
     /// Returns this Port's 0-based index.
@@ -168,6 +372,116 @@ impl<M: AnyBitMode> Port<M> {
         self.write_control(ControlReq::Reset, flags.bits(), &[])
     }
 
+    /// Reads received data from the port.
+    ///
+    /// This blocks until at least 1 Byte of data is available, or the USB timeout elapses. Returns
+    /// the number of Bytes written to `buf`.
+    ///
+    /// The device prefixes every USB packet with a modem status header. The header is stripped
+    /// from the returned data, but its contents are recorded and can be queried without another
+    /// USB request.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.wait_rx()?;
+
+        Ok(self.rx.take(buf))
+    }
+
+    /// Blocks until received data is available in `self.rx`, or the USB timeout elapses.
+    pub(crate) fn wait_rx(&mut self) -> Result<()> {
+        let start = Instant::now();
+        while self.rx.pending.is_empty() {
+            // The device sends status-only packets whenever the latency timer expires, so keep
+            // reading until we get payload data.
+            let remaining = self
+                .timeout
+                .checked_sub(start.elapsed())
+                .filter(|t| !t.is_zero())
+                .ok_or_else(|| Error::from_kind(ErrorKind::Timeout))?;
+            self.fill_rx(remaining)?;
+        }
+
+        Ok(())
+    }
+
+    /// Performs a single bulk IN transfer, adding the received data to `self.rx`.
+    fn fill_rx(&mut self, timeout: Duration) -> Result<()> {
+        let mut transfer = vec![0; self.rx_transfer_size()];
+        let n = self.dev().read_bulk(self.ep_in, &mut transfer, timeout)?;
+
+        self.rx
+            .ingest(&transfer[..n], usize::from(self.max_packet_size));
+        Ok(())
+    }
+
+    /// Writes data to the port.
+    ///
+    /// The data is split into chunks that fit into the device's TX buffer, which are sent one
+    /// after another. If a chunk is only partially transferred, or an error occurs after some data
+    /// was already sent, this returns the number of Bytes that were written. An error is only
+    /// returned if no data could be written at all.
+    pub fn write(&mut self, data: &[u8]) -> Result<usize> {
+        let mut written = 0;
+        for chunk in data.chunks(self.tx_chunk_size()) {
+            match self.dev().write_bulk(self.ep_out, chunk, self.timeout) {
+                Ok(n) => {
+                    written += n;
+                    if n < chunk.len() {
+                        log::debug!("short write ({} of {} bytes)", n, chunk.len());
+                        break;
+                    }
+                }
+                Err(e) if written == 0 => return Err(e),
+                Err(e) => {
+                    log::debug!("write failed after {} bytes: {}", written, e);
+                    break;
+                }
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Writes all of `data` to the port.
+    ///
+    /// If this fails, the returned error's `bytes_written` method reports how much of `data` was
+    /// sent to the device before the error occurred.
+    pub fn write_all(&mut self, mut data: &[u8]) -> Result<()> {
+        let mut written = 0;
+        while !data.is_empty() {
+            let n = self
+                .write(data)
+                .map_err(|e| e.with_bytes_written(written))?;
+            if n == 0 {
+                // The device did not accept any data, retrying would loop forever.
+                return Err(Error::from_kind(ErrorKind::Timeout).with_bytes_written(written));
+            }
+            written += n;
+            data = &data[n..];
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of Bytes to receive in a single bulk IN transfer.
+    pub(crate) fn rx_transfer_size(&self) -> usize {
+        let packet_size = usize::from(self.max_packet_size);
+        // Read up to a full RX buffer worth of packets at once.
+        let packets = cmp::max(usize::from(self.properties.rx_buf) / packet_size, 1);
+        packets * packet_size
+    }
+
+    /// Returns the number of Bytes to send in a single bulk OUT transfer.
+    fn tx_chunk_size(&self) -> usize {
+        let packet_size = usize::from(self.max_packet_size);
+        // Round the TX buffer size down to full packets.
+        let packets = cmp::max(usize::from(self.properties.tx_buf) / packet_size, 1);
+        packets * packet_size
+    }
+
     /// Polls the current status of the lower 8 I/O pins.
     ///
     /// **Note**: This only returns the low 8 bits. If the port has more than 8 data pins, the upper
@@ -180,6 +494,124 @@ impl<M: AnyBitMode> Port<M> {
     }
 }
 
+impl Port<bitmode::Bitbang> {
+    /// Sets the level of the output pins.
+    ///
+    /// Bits of `value` that correspond to input pins are ignored. The pin levels can be read back
+    /// with [`read_pins`].
+    ///
+    /// [`read_pins`]: #method.read_pins
+    pub fn write_pins(&mut self, value: u8) -> Result<()> {
+        self.write_all(&[value])
+    }
+
+    /// Drives a sequence of pin states.
+    ///
+    /// Each Byte of `pattern` is applied to the output pins in turn. The rate at which this
+    /// happens is derived from the baud rate, which can be configured with
+    /// [`Port::set_baud_rate`] before switching to bit-bang mode.
+    ///
+    /// [`Port::set_baud_rate`]: struct.Port.html#method.set_baud_rate
+    pub fn write_pattern(&mut self, pattern: &[u8]) -> Result<()> {
+        self.write_all(pattern)
+    }
+}
+
+impl Port<bitmode::Syncbb> {
+    /// Drives a sequence of pin states and samples the pins along with each of them.
+    ///
+    /// Each Byte of `pattern` is applied to the output pins in turn, and for each Byte, the device
+    /// samples all pins once. The samples are returned in the same order, so the returned `Vec`
+    /// always has the same length as `pattern`.
+    ///
+    /// The pattern is sent in chunks that fit into the device's buffers, so arbitrarily long
+    /// patterns are supported. Like in asynchronous bit-bang mode, the pin update rate is derived
+    /// from the baud rate.
+    pub fn transfer(&mut self, pattern: &[u8]) -> Result<Vec<u8>> {
+        // The samples of a chunk have to fit into the RX buffer, or the device stops clocking.
+        let chunk_size = cmp::min(self.tx_chunk_size(), usize::from(self.properties.rx_buf));
+        let mut samples = vec![0; pattern.len()];
+        let mut received = 0;
+        for chunk in pattern.chunks(chunk_size) {
+            self.write_all(chunk)?;
+
+            let end = received + chunk.len();
+            while received < end {
+                self.wait_rx()?;
+                received += self.rx.take(&mut samples[received..end]);
+            }
+        }
+
+        Ok(samples)
+    }
+}
+
+impl Port<bitmode::Cbus> {
+    /// Drives the CBUS pins.
+    ///
+    /// In CBUS bit-bang mode, up to 4 CBUS pins can be used as GPIOs: bits 0-3 of `direction`
+    /// select which of them are outputs, and the same bits of `values` set the output levels.
+    /// The bits map to CBUS0-CBUS3 on FT232R and FT-X devices, and to ACBUS5, ACBUS6, ACBUS8 and
+    /// ACBUS9 on the FT232H.
+    ///
+    /// A pin only acts as a GPIO if it is configured for I/O mode in the EEPROM (see
+    /// [`EepromConfig::set_cbus`]). This method checks the EEPROM and returns an error of kind
+    /// `Eeprom` if an output pin is not configured accordingly, and logs a warning if an input pin
+    /// is not.
+    ///
+    /// Returns an error of kind `Other` if `direction` or `values` have any of the upper 4 bits
+    /// set.
+    ///
+    /// [`EepromConfig::set_cbus`]: eeprom/struct.EepromConfig.html#method.set_cbus
+    pub fn set_pins(&mut self, direction: u8, values: u8) -> Result<()> {
+        if direction > 0xF || values > 0xF {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "CBUS pin mask out of range (direction 0x{:02x}, values 0x{:02x})",
+                    direction, values
+                ),
+            ));
+        }
+
+        let io = self.cbus_io_pins()?;
+        if direction & !io != 0 {
+            return Err(Error::new(
+                ErrorKind::Eeprom,
+                format!(
+                    "CBUS pins 0b{:04b} are not configured for I/O mode in the EEPROM",
+                    direction & !io
+                ),
+            ));
+        }
+        let unused = !direction & !io & 0xF;
+        if unused != 0 {
+            log::warn!(
+                "CBUS pins 0b{:04b} are not configured for I/O mode and can not be read",
+                unused
+            );
+        }
+
+        self.set_bitmode(BitMode::Cbus, direction << 4 | values)
+    }
+
+    /// Reads the current level of the 4 CBUS bit-bang pins.
+    ///
+    /// The pins are returned in bits 0-3, in the same order as for [`set_pins`]. The level of pins
+    /// that are not configured for I/O mode in the EEPROM is unspecified.
+    ///
+    /// This is not called `read`, since that name is taken by [`Port::read`], which reads received
+    /// data in every mode. Unlike [`read_pins`], the upper 4 bits (which are not CBUS pins) are
+    /// masked off.
+    ///
+    /// [`set_pins`]: #method.set_pins
+    /// [`Port::read`]: #method.read
+    /// [`read_pins`]: #method.read_pins
+    pub fn read_cbus(&self) -> Result<u8> {
+        Ok(self.read_pins()? & 0xF)
+    }
+}
+
 impl<M: AnyBitMode> fmt::Debug for Port<M> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mode = type_name::<M>();
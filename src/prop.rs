@@ -4,6 +4,8 @@
 pub(crate) struct DeviceProps {
     /// Device model name.
     pub model: &'static str,
+    /// Chip family, for things that differ between device generations.
+    pub chip: ChipType,
     /// TX buffer size in Bytes.
     pub tx_buf: u16,
     /// RX buffer size in Bytes.
@@ -14,6 +16,29 @@ pub(crate) struct DeviceProps {
     pub ports: &'static [PortProps],
 }
 
+/// FTDI chip families.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum ChipType {
+    Am,
+    Bm,
+    FT2232C,
+    R,
+    FT2232H,
+    FT4232H,
+    FT232H,
+    X,
+}
+
+impl ChipType {
+    /// Returns whether this is a high-speed `-H` chip.
+    pub fn is_h(self) -> bool {
+        matches!(
+            self,
+            ChipType::FT2232H | ChipType::FT4232H | ChipType::FT232H
+        )
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct PortProps {
     pub mpsse: MpsseSupport,
@@ -45,6 +70,7 @@ pub(crate) static DEVICES: &[Option<DeviceProps>] = &[
     // 2.00
     Some(DeviceProps {
         model: "FT232AM",
+        chip: ChipType::Am,
         tx_buf: 128,
         rx_buf: 128,
         port_width: 0, // UART only
@@ -54,6 +80,7 @@ pub(crate) static DEVICES: &[Option<DeviceProps>] = &[
     // 4.00
     Some(DeviceProps {
         model: "FT232BM",
+        chip: ChipType::Bm,
         tx_buf: 128,
         rx_buf: 384,
         port_width: 0, // UART only
@@ -62,6 +89,7 @@ pub(crate) static DEVICES: &[Option<DeviceProps>] = &[
     // 5.00
     Some(DeviceProps {
         model: "FT2232C/D",
+        chip: ChipType::FT2232C,
         tx_buf: 128,
         rx_buf: 384,
         port_width: 12, // xDBUS0-7, xCBUS0-3
@@ -77,6 +105,7 @@ pub(crate) static DEVICES: &[Option<DeviceProps>] = &[
     // 6.00
     Some(DeviceProps {
         model: "FT232R",
+        chip: ChipType::R,
         tx_buf: 256,
         rx_buf: 128,
         port_width: 8,
@@ -85,6 +114,7 @@ pub(crate) static DEVICES: &[Option<DeviceProps>] = &[
     // 7.00
     Some(DeviceProps {
         model: "FT2232H",
+        chip: ChipType::FT2232H,
         tx_buf: 4096,
         rx_buf: 4096,
         port_width: 16, // Has 2 16-bit ports.
@@ -101,6 +131,7 @@ pub(crate) static DEVICES: &[Option<DeviceProps>] = &[
     // 8.00
     Some(DeviceProps {
         model: "FT4232H",
+        chip: ChipType::FT4232H,
         tx_buf: 2048,
         rx_buf: 2048,
         port_width: 8, // Has 4 8-bit ports.
@@ -122,6 +153,7 @@ pub(crate) static DEVICES: &[Option<DeviceProps>] = &[
     // 9.00
     Some(DeviceProps {
         model: "FT232H",
+        chip: ChipType::FT232H,
         tx_buf: 1024,
         rx_buf: 1024,
         port_width: 16, // Has 1 16-bit port.
@@ -132,9 +164,26 @@ pub(crate) static DEVICES: &[Option<DeviceProps>] = &[
     // 10.00
     Some(DeviceProps {
         model: "FT-X",
+        chip: ChipType::X,
         tx_buf: 512,
         rx_buf: 512,
         port_width: 8,
         ports: DUMB_PORT,
     }),
 ];
+
+/// Looks up the properties of the device with the given `bcdDevice` version.
+pub(crate) fn lookup(device_version: u16) -> Option<&'static DeviceProps> {
+    if device_version & 0xFF != 0 {
+        return None;
+    }
+
+    // `bcdDevice` major version
+    let major = (device_version >> 12) * 10 + (device_version >> 8 & 0xF);
+    DEVICES.get(usize::from(major))?.as_ref()
+}
+
+/// Looks up the properties of the device with the given model name.
+pub(crate) fn by_model(model: &str) -> Option<&'static DeviceProps> {
+    DEVICES.iter().flatten().find(|props| props.model == model)
+}
@@ -1,10 +1,5 @@
 //! Includes `README.md` as a doc comment so we test examples in it.
 
-macro_rules! doc {
-    ($e:expr) => {
-        #[doc = $e]
-        extern {}
-    };
-}
-
-doc!(include_str!("../README.md"));
+#[doc = include_str!("../README.md")]
+#[cfg(doctest)]
+pub struct ReadmeDoctests;
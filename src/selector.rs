@@ -0,0 +1,394 @@
+//! Selecting devices by their properties.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::{Error, ErrorKind, Ftdi, Result, PIDS_FTDI, VID_FTDI};
+
+type Device = rusb::Device<rusb::GlobalContext>;
+
+/// Timeout for reading string descriptors while matching devices.
+const STRING_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Selects FTDI devices by their properties.
+///
+/// Criteria are added with the builder methods, and a device has to match all of them to be
+/// selected. If no VID or PID is given, devices with the IDs of FTDI's official products are
+/// selected.
+///
+/// Matching the serial number or product string requires briefly opening the device to read its
+/// string descriptors.
+///
+/// # Example
+///
+/// ```no_run
+/// use rftdi::DeviceSelector;
+///
+/// # fn main() -> rftdi::Result<()> {
+/// let ftdi = DeviceSelector::new()
+///     .product_contains("Dual RS232")
+///     .port_path("1-4.2")
+///     .open()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DeviceSelector {
+    vid: Option<u16>,
+    pid: Option<u16>,
+    serial: Option<String>,
+    product: Option<StringMatch>,
+    port_path: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum StringMatch {
+    Exact(String),
+    Contains(String),
+}
+
+impl StringMatch {
+    fn matches(&self, s: &str) -> bool {
+        match self {
+            StringMatch::Exact(expected) => s == expected,
+            StringMatch::Contains(expected) => s.contains(expected.as_str()),
+        }
+    }
+}
+
+impl DeviceSelector {
+    /// Creates a selector that matches all official FTDI devices.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only selects devices with the given USB Vendor ID.
+    pub fn vid(mut self, vid: u16) -> Self {
+        self.vid = Some(vid);
+        self
+    }
+
+    /// Only selects devices with the given USB Product ID.
+    pub fn pid(mut self, pid: u16) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Only selects devices whose serial number string is exactly `serial`.
+    pub fn serial(mut self, serial: &str) -> Self {
+        self.serial = Some(serial.to_string());
+        self
+    }
+
+    /// Only selects devices whose product string is exactly `product`.
+    pub fn product(mut self, product: &str) -> Self {
+        self.product = Some(StringMatch::Exact(product.to_string()));
+        self
+    }
+
+    /// Only selects devices whose product string contains `substring`.
+    pub fn product_contains(mut self, substring: &str) -> Self {
+        self.product = Some(StringMatch::Contains(substring.to_string()));
+        self
+    }
+
+    /// Only selects the device attached to the given physical USB port.
+    ///
+    /// The path consists of the bus number, followed by a dash and the dot-separated list of port
+    /// numbers leading from the root hub to the device (eg. `1-4.2`). This is the same format used
+    /// by Linux in sysfs. Unlike the device address, the path stays the same when the device is
+    /// replugged into the same port.
+    pub fn port_path(mut self, path: &str) -> Self {
+        self.port_path = Some(path.to_string());
+        self
+    }
+
+    /// Opens the only device matching this selector.
+    ///
+    /// If no device or multiple devices match, the returned error describes which criteria
+    /// matched how many devices.
+    pub fn open(&self) -> Result<Ftdi> {
+        let mut stats = MatchStats::default();
+        let result = Ftdi::open_filtered(|dev| Ok(self.matches(dev, &mut stats)));
+
+        result.map_err(|e| match e.kind() {
+            ErrorKind::NoDeviceFound | ErrorKind::MultipleDevicesFound => {
+                Error::new(e.kind(), stats.describe(self))
+            }
+            _ => e,
+        })
+    }
+
+    /// Returns an iterator over all devices matching this selector.
+    ///
+    /// This will try to open every matching device. If no device matches, an error of kind
+    /// `NoDeviceFound` is returned, describing which criteria matched how many devices.
+    pub fn devices(&self) -> Result<impl Iterator<Item = Result<Ftdi>>> {
+        let mut stats = MatchStats::default();
+        let devices = crate::devices_filtered(|dev| Ok(self.matches(dev, &mut stats)))?;
+        if stats.matched.is_empty() {
+            return Err(Error::new(ErrorKind::NoDeviceFound, stats.describe(self)));
+        }
+        Ok(devices)
+    }
+
+    /// Returns whether `dev` matches all criteria of this selector.
+    pub(crate) fn matches_device(&self, dev: &Device) -> bool {
+        self.matches(dev, &mut MatchStats::default())
+    }
+
+    fn matches(&self, dev: &Device, stats: &mut MatchStats) -> bool {
+        let ids_match = match dev.device_descriptor() {
+            Ok(descr) => match (self.vid, self.pid) {
+                (None, None) => {
+                    descr.vendor_id() == VID_FTDI && PIDS_FTDI.contains(&descr.product_id())
+                }
+                (vid, pid) => {
+                    vid.is_none_or(|vid| vid == descr.vendor_id())
+                        && pid.is_none_or(|pid| pid == descr.product_id())
+                }
+            },
+            Err(e) => {
+                log::debug!("failed to read device descriptor of {:?}: {}", dev, e);
+                false
+            }
+        };
+
+        let path_matches = match &self.port_path {
+            Some(path) => port_path(dev).as_deref() == Some(path.as_str()),
+            None => true,
+        };
+
+        // Only open devices with matching IDs to read their strings. Other devices likely aren't
+        // FTDI devices at all.
+        let (serial_matches, product_matches) =
+            if ids_match && (self.serial.is_some() || self.product.is_some()) {
+                self.match_strings(dev)
+            } else {
+                (self.serial.is_none(), self.product.is_none())
+            };
+
+        stats.ids += ids_match as usize;
+        stats.serial += (ids_match && serial_matches) as usize;
+        stats.product += (ids_match && product_matches) as usize;
+        stats.port_path += path_matches as usize;
+
+        let matches = ids_match && serial_matches && product_matches && path_matches;
+        if matches {
+            stats.matched.push(describe_device(dev));
+        }
+        matches
+    }
+
+    /// Returns whether the serial number and product strings of `dev` match.
+    fn match_strings(&self, dev: &Device) -> (bool, bool) {
+        let strings = dev.device_descriptor().and_then(|descr| {
+            let handle = dev.open()?;
+            let language = handle
+                .read_languages(STRING_TIMEOUT)?
+                .into_iter()
+                .next()
+                .ok_or(rusb::Error::NotFound)?;
+            let serial = match self.serial {
+                Some(_) => {
+                    Some(handle.read_serial_number_string(language, &descr, STRING_TIMEOUT)?)
+                }
+                None => None,
+            };
+            let product = match self.product {
+                Some(_) => Some(handle.read_product_string(language, &descr, STRING_TIMEOUT)?),
+                None => None,
+            };
+            Ok((serial, product))
+        });
+
+        match strings {
+            Ok((serial, product)) => (
+                match (&self.serial, serial) {
+                    (Some(expected), Some(serial)) => *expected == serial,
+                    _ => true,
+                },
+                match (&self.product, product) {
+                    (Some(expected), Some(product)) => expected.matches(&product),
+                    _ => true,
+                },
+            ),
+            Err(e) => {
+                log::debug!("failed to read strings of {:?}: {}", dev, e);
+                (self.serial.is_none(), self.product.is_none())
+            }
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.criteria().join(", "))
+    }
+}
+
+impl DeviceSelector {
+    fn ids_criterion(&self) -> String {
+        match (self.vid, self.pid) {
+            (None, None) => String::from("FTDI VID/PID"),
+            (Some(vid), None) => format!("VID {:04x}", vid),
+            (None, Some(pid)) => format!("PID {:04x}", pid),
+            (Some(vid), Some(pid)) => format!("VID/PID {:04x}:{:04x}", vid, pid),
+        }
+    }
+
+    fn serial_criterion(&self) -> Option<String> {
+        self.serial
+            .as_ref()
+            .map(|serial| format!("serial {:?}", serial))
+    }
+
+    fn product_criterion(&self) -> Option<String> {
+        self.product.as_ref().map(|product| match product {
+            StringMatch::Exact(s) => format!("product {:?}", s),
+            StringMatch::Contains(s) => format!("product containing {:?}", s),
+        })
+    }
+
+    fn port_path_criterion(&self) -> Option<String> {
+        self.port_path
+            .as_ref()
+            .map(|path| format!("port path {}", path))
+    }
+
+    fn criteria(&self) -> Vec<String> {
+        let mut criteria = vec![self.ids_criterion()];
+        criteria.extend(self.serial_criterion());
+        criteria.extend(self.product_criterion());
+        criteria.extend(self.port_path_criterion());
+        criteria
+    }
+}
+
+/// Number of devices matching each criterion of a `DeviceSelector`.
+#[derive(Default)]
+struct MatchStats {
+    ids: usize,
+    serial: usize,
+    product: usize,
+    port_path: usize,
+    /// Descriptions of the devices matching all criteria.
+    matched: Vec<String>,
+}
+
+impl MatchStats {
+    fn describe(&self, selector: &DeviceSelector) -> String {
+        if self.matched.len() > 1 {
+            return format!(
+                "{} devices match {}: {}",
+                self.matched.len(),
+                selector,
+                self.matched.join(", ")
+            );
+        }
+
+        let mut counts = vec![format!("{} matched {}", self.ids, selector.ids_criterion())];
+        // Strings are only checked for devices with matching IDs.
+        if let Some(criterion) = selector.serial_criterion() {
+            counts.push(format!("{} of those matched {}", self.serial, criterion));
+        }
+        if let Some(criterion) = selector.product_criterion() {
+            counts.push(format!("{} of those matched {}", self.product, criterion));
+        }
+        if let Some(criterion) = selector.port_path_criterion() {
+            counts.push(format!(
+                "{} device(s) matched {}",
+                self.port_path, criterion
+            ));
+        }
+
+        format!("no device matches {} ({})", selector, counts.join(", "))
+    }
+}
+
+/// Returns the physical port path of `dev` (eg. `1-4.2`).
+pub(crate) fn port_path(dev: &Device) -> Option<String> {
+    let ports = dev.port_numbers().ok()?;
+    let ports = ports
+        .iter()
+        .map(|port| port.to_string())
+        .collect::<Vec<_>>();
+    Some(format!("{}-{}", dev.bus_number(), ports.join(".")))
+}
+
+fn describe_device(dev: &Device) -> String {
+    match port_path(dev) {
+        Some(path) => format!(
+            "bus {} address {} (port path {})",
+            dev.bus_number(),
+            dev.address(),
+            path
+        ),
+        None => format!("bus {} address {}", dev.bus_number(), dev.address()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn product_matching() {
+        let exact = StringMatch::Exact(String::from("Dual RS232-HS"));
+        assert!(exact.matches("Dual RS232-HS"));
+        assert!(!exact.matches("Dual RS232-HS "));
+        assert!(!exact.matches("Dual RS232"));
+
+        let contains = StringMatch::Contains(String::from("RS232"));
+        assert!(contains.matches("Dual RS232-HS"));
+        assert!(contains.matches("RS232"));
+        assert!(!contains.matches("rs232"));
+    }
+
+    #[test]
+    fn describe_no_match() {
+        let selector = DeviceSelector::new()
+            .serial("A1234567")
+            .product_contains("RS232")
+            .port_path("1-4.2");
+        let stats = MatchStats {
+            ids: 3,
+            serial: 1,
+            product: 2,
+            port_path: 0,
+            matched: Vec::new(),
+        };
+        assert_eq!(
+            stats.describe(&selector),
+            "no device matches FTDI VID/PID, serial \"A1234567\", product containing \"RS232\", \
+             port path 1-4.2 (3 matched FTDI VID/PID, 1 of those matched serial \"A1234567\", \
+             2 of those matched product containing \"RS232\", 0 device(s) matched port path 1-4.2)"
+        );
+
+        let selector = DeviceSelector::new().vid(0x0403).pid(0x6010);
+        assert_eq!(
+            MatchStats::default().describe(&selector),
+            "no device matches VID/PID 0403:6010 (0 matched VID/PID 0403:6010)"
+        );
+    }
+
+    #[test]
+    fn describe_multiple_matches() {
+        let selector = DeviceSelector::new().product("FT232R USB UART");
+        let stats = MatchStats {
+            ids: 2,
+            serial: 2,
+            product: 2,
+            port_path: 2,
+            matched: vec![
+                String::from("bus 1 address 4"),
+                String::from("bus 1 address 7 (port path 1-2)"),
+            ],
+        };
+        assert_eq!(
+            stats.describe(&selector),
+            "2 devices match FTDI VID/PID, product \"FT232R USB UART\": bus 1 address 4, \
+             bus 1 address 7 (port path 1-2)"
+        );
+    }
+}
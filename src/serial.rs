@@ -1,7 +1,11 @@
-use crate::{bitmode, ControlReq, Port, Result};
+use crate::prop::ChipType;
+use crate::{bitmode, ControlReq, Error, ErrorKind, Port, Result};
 use bitflags::bitflags;
+use std::time::{Duration, Instant};
+use std::{cmp, io, thread};
 
 bitflags! {
+    #[derive(Default)]
     pub struct ModemStatus: u16 {
         /// Clear to send.
         const CTS = 1 << 4;
@@ -27,25 +31,24 @@ bitflags! {
         const TEMT = 1 << 14;
         /// Error in RECV FIFO.
         const ERR = 1 << 15;
+
+        /// All line error bits (OE, PE, FE and BI).
+        const LINE_ERRORS = Self::OE.bits | Self::PE.bits | Self::FE.bits | Self::BI.bits;
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
 pub enum FlowControl {
+    #[default]
     Disabled,
     RtsCts,
     DtrDsr,
     XonXoff,
 }
 
-impl Default for FlowControl {
-    fn default() -> Self {
-        FlowControl::Disabled
-    }
-}
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
 pub enum Parity {
+    #[default]
     None = 0x00,
     Odd = 0x01,
     Even = 0x02,
@@ -53,22 +56,132 @@ pub enum Parity {
     Space = 0x04,
 }
 
-impl Default for Parity {
-    fn default() -> Self {
-        Parity::None
-    }
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum DataBits {
+    Seven = 7,
+    #[default]
+    Eight = 8,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
 pub enum StopBits {
+    #[default]
     Stop1 = 0x00,
     Stop15 = 0x01,
     Stop2 = 0x02,
 }
 
-impl Default for StopBits {
+/// A complete UART configuration.
+///
+/// The default configuration is 9600 Baud 8N1 without flow control, which is also what the device
+/// uses after a reset.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub flow_control: FlowControl,
+}
+
+impl Default for SerialConfig {
     fn default() -> Self {
-        StopBits::Stop1
+        Self {
+            baud_rate: 9600,
+            data_bits: DataBits::default(),
+            parity: Parity::default(),
+            stop_bits: StopBits::default(),
+            flow_control: FlowControl::default(),
+        }
+    }
+}
+
+/// Maps the 3 fractional divisor bits (in eighths) to their encoding in the `SetBaudrate` value.
+const BAUD_FRAC_CODE: [u32; 8] = [0, 3, 2, 4, 1, 5, 6, 7];
+
+/// Computes the baud rate divisor for the FT232AM.
+///
+/// The AM only supports the sub-integer divisors 0, 0.125, 0.25 and 0.5, and no divisors between
+/// 1 and 2.
+///
+/// Returns the achieved baud rate and the encoded divisor.
+fn am_divisor(baud: u32) -> (u32, u32) {
+    const ADJUST_UP: [u32; 8] = [0, 0, 0, 1, 0, 3, 2, 1];
+    const ADJUST_DOWN: [u32; 8] = [0, 0, 0, 1, 0, 1, 2, 3];
+    // Divisors are in eighths of the 3 MHz base clock.
+    const CLK: u32 = 24_000_000;
+
+    let divisor = CLK / baud;
+    let divisor = divisor - ADJUST_DOWN[(divisor & 7) as usize];
+
+    // Integer division rounds down, so try the next divisor too.
+    let mut best: Option<(u32, u32, u32)> = None; // (divisor, baud, error)
+    for candidate in divisor..=divisor + 1 {
+        let candidate = if candidate <= 8 {
+            8
+        } else if divisor < 16 {
+            16
+        } else {
+            cmp::min(candidate + ADJUST_UP[(candidate & 7) as usize], 0x1FFF8)
+        };
+
+        let estimate = (CLK + candidate / 2) / candidate;
+        let error = estimate.abs_diff(baud);
+        if best.is_none_or(|(_, _, best_error)| error < best_error) {
+            best = Some((candidate, estimate, error));
+        }
+    }
+
+    let (divisor, baud, _) = best.unwrap();
+    let mut encoded = divisor >> 3 | BAUD_FRAC_CODE[(divisor & 7) as usize] << 14;
+    if encoded == 1 {
+        // A divisor of 1 is encoded as 0 (3 MBaud).
+        encoded = 0;
+    }
+    (baud, encoded)
+}
+
+/// Computes the baud rate divisor for chips with 8 sub-integer divisors.
+///
+/// `clk / clk_div` is the base clock the divisor applies to.
+///
+/// Returns the achieved baud rate and the encoded divisor.
+fn frac_divisor(baud: u32, clk: u32, clk_div: u32) -> (u32, u32) {
+    let base = clk / clk_div;
+
+    // Divisors 1, 1.5 and 2 have special encodings, and divisors in between aren't supported.
+    if baud >= base {
+        (base, 0)
+    } else if baud >= clk / (clk_div + clk_div / 2) {
+        (clk / (clk_div + clk_div / 2), 1)
+    } else if baud >= base / 2 {
+        (base / 2, 2)
+    } else {
+        // Divide in sixteenths to get 3 fractional bits and one bit for rounding.
+        let divisor = u64::from(base) * 16 / u64::from(baud);
+        let divisor = cmp::min(divisor.div_ceil(2), 0x1FFFF) as u32;
+        let baud = (u64::from(base) * 16 / u64::from(divisor)).div_ceil(2) as u32;
+        let encoded = divisor >> 3 | BAUD_FRAC_CODE[(divisor & 7) as usize] << 14;
+        (baud, encoded)
+    }
+}
+
+/// Computes the `SetBaudrate` divisor for `chip`.
+///
+/// Returns the achieved baud rate and the encoded divisor (up to 18 bits).
+fn baud_divisor(chip: ChipType, baud: u32) -> (u32, u32) {
+    const H_CLK: u32 = 120_000_000;
+    const C_CLK: u32 = 48_000_000;
+
+    match chip {
+        ChipType::Am => am_divisor(baud),
+        // Same threshold as libftdi, so that both pick the same divisor.
+        _ if chip.is_h() && u64::from(baud) * 10 > u64::from(H_CLK / 0x3FFF) => {
+            // `-H` chips can use a 12 MHz base clock by disabling the divide-by-5 prescaler.
+            let (baud, encoded) = frac_divisor(baud, H_CLK, 10);
+            (baud, encoded | 1 << 17)
+        }
+        _ => frac_divisor(baud, C_CLK, 16),
     }
 }
 
@@ -79,12 +192,108 @@ const MODEM_CTRL_SET_RTS_LOW: u16 = 0x0200;
 
 /// Functionality available when in serial mode.
 impl Port<bitmode::Serial> {
+    /// Sets the baud rate of the port.
+    ///
+    /// Not every baud rate can be generated exactly. This will configure the closest baud rate
+    /// supported by the device and return it. The caller can compare it to `baud` to decide
+    /// whether the error is acceptable.
+    ///
+    /// # Parameters
+    ///
+    /// * **`baud`**: The desired baud rate. Must not be 0, or this method will panic.
+    pub fn set_baud_rate(&mut self, baud: u32) -> Result<u32> {
+        assert!(baud != 0, "baud rate must not be 0");
+
+        let chip = self.properties().chip;
+        let (actual, encoded) = baud_divisor(chip, baud);
+        log::debug!(
+            "set_baud_rate: requested {}, achieved {}, divisor {:#07x}",
+            baud,
+            actual,
+            encoded
+        );
+
+        let value = encoded as u16;
+        let high = (encoded >> 16) as u16;
+        // Multi-port and `-H` chips put the upper divisor bits in the upper byte of `wIndex`,
+        // next to the interface number. Single-port chips put them in the lower byte.
+        let index = if chip.is_h() || self.properties().ports.len() > 1 {
+            high << 8 | self.interface_number()
+        } else {
+            high
+        };
+
+        self.write_control_with_index(ControlReq::SetBaudrate, value, index, &[])?;
+        if let Some(config) = &mut self.serial_config {
+            config.baud_rate = actual;
+        }
+        Ok(actual)
+    }
+
+    /// Applies a complete UART configuration to the port.
+    ///
+    /// On success, `config` becomes the port's cached configuration (with the baud rate replaced
+    /// by the one actually achieved), which can be read back with `serial_config`. If any part of
+    /// the configuration fails to apply, the device is left in an unknown state and the cached
+    /// configuration is cleared.
+    pub fn apply_serial_config(&mut self, config: &SerialConfig) -> Result<()> {
+        self.serial_config = None;
+
+        let baud_rate = self.set_baud_rate(config.baud_rate)?;
+        self.set_serial_config(config.data_bits, config.parity, config.stop_bits, false)?;
+        self.set_flow_control(config.flow_control)?;
+
+        self.serial_config = Some(SerialConfig {
+            baud_rate,
+            ..*config
+        });
+        Ok(())
+    }
+
+    /// Returns the UART configuration last applied with `apply_serial_config`.
+    ///
+    /// Changes made with the individual setters (eg. `set_baud_rate`) are reflected in the
+    /// returned configuration. Returns `None` if no complete configuration was applied yet.
+    pub fn serial_config(&self) -> Option<&SerialConfig> {
+        self.serial_config.as_ref()
+    }
+
+    /// Returns the modem status received along with the most recent data packet.
+    ///
+    /// Unlike `poll_modem_status`, this does not perform any USB transfer. The status is updated
+    /// whenever `read` receives data from the device.
+    pub fn last_modem_status(&self) -> ModemStatus {
+        self.rx.modem_status
+    }
+
+    /// Returns and clears the line errors (OE, PE, FE and BI) received since the last call.
+    ///
+    /// Line errors are collected by `read` from every received packet, so none of them are lost
+    /// even if the status changes between packets.
+    pub fn take_line_errors(&mut self) -> ModemStatus {
+        self.rx.take_line_errors()
+    }
+
     pub fn poll_modem_status(&self) -> Result<ModemStatus> {
         let mut buf = [0; 2];
         self.read_control(ControlReq::PollModemStatus, 0, &mut buf)?;
         Ok(ModemStatus::from_bits_truncate(u16::from_le_bytes(buf)))
     }
 
+    /// Blocks until the device has transmitted all data in its TX FIFO, or the USB timeout
+    /// elapses.
+    pub(crate) fn wait_tx_empty(&mut self) -> Result<()> {
+        let start = Instant::now();
+        while !self.poll_modem_status()?.contains(ModemStatus::TEMT) {
+            if start.elapsed() >= self.timeout() {
+                return Err(Error::from_kind(ErrorKind::Timeout));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        Ok(())
+    }
+
     /// Sets or clears the Data Terminal Ready (DTR) bit.
     ///
     /// Note that the DTR output pin is inverted (DTR#), so the pin state will be the opposite of
@@ -119,23 +328,32 @@ impl Port<bitmode::Serial> {
             FlowControl::XonXoff => 0x0400,
         };
 
-        self.write_control(ControlReq::SetFlowCtrl, value, &[])
+        self.write_control(ControlReq::SetFlowCtrl, value, &[])?;
+        if let Some(config) = &mut self.serial_config {
+            config.flow_control = flow;
+        }
+        Ok(())
     }
 
     pub fn set_serial_config(
         &mut self,
+        data_bits: DataBits,
         parity: Parity,
         stop: StopBits,
         break_condition: bool,
     ) -> Result<()> {
-        // FIXME: Apparently this can also set the word size?
-
-        let parity = parity as u16;
-        let stop = stop as u16;
-        let break_condition = break_condition as u16;
-        let value = parity << 8 | stop << 11 | break_condition << 14;
+        let value = data_bits as u16
+            | (parity as u16) << 8
+            | (stop as u16) << 11
+            | (break_condition as u16) << 14;
 
-        self.write_control(ControlReq::SetData, value, &[])
+        self.write_control(ControlReq::SetData, value, &[])?;
+        if let Some(config) = &mut self.serial_config {
+            config.data_bits = data_bits;
+            config.parity = parity;
+            config.stop_bits = stop;
+        }
+        Ok(())
     }
 
     pub fn set_event_char(&mut self, event: Option<u8>) -> Result<()> {
@@ -167,3 +385,31 @@ impl Port<bitmode::Serial> {
         self.write_control(ControlReq::SetLatencyTimer, time.into(), &[])
     }
 }
+
+impl io::Read for Port<bitmode::Serial> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(Port::read(self, buf)?)
+    }
+}
+
+impl io::BufRead for Port<bitmode::Serial> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.wait_rx()?;
+        Ok(self.rx.pending.make_contiguous())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.rx.pending.drain(..amt);
+    }
+}
+
+impl io::Write for Port<bitmode::Serial> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(Port::write(self, buf)?)
+    }
+
+    /// Blocks until the device has transmitted all data in its TX FIFO.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(self.wait_tx_empty()?)
+    }
+}
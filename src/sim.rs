@@ -0,0 +1,588 @@
+//! A simulated FTDI device for testing without hardware.
+//!
+//! [`SimDevice`] implements [`Backend`] and models the vendor requests used by `rftdi`, an
+//! in-memory EEPROM, and a loopback connection from each port's bulk OUT to its bulk IN endpoint.
+//! It can be passed to [`Ftdi::from_backend`] like any other backend:
+//!
+//! ```
+//! use rftdi::{sim::SimDevice, Ftdi};
+//!
+//! # fn main() -> rftdi::Result<()> {
+//! let ftdi = Ftdi::from_backend(SimDevice::new("FT2232H")?)?;
+//! let mut port = ftdi.open_port(0)?;
+//! port.write_all(b"hello")?;
+//!
+//! let mut buf = [0; 5];
+//! let n = port.read(&mut buf)?;
+//! assert_eq!(&buf[..n], &b"hello"[..n]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! This module is only available when the `sim` Cargo feature is enabled.
+//!
+//! [`SimDevice`]: struct.SimDevice.html
+//! [`Backend`]: ../backend/trait.Backend.html
+//! [`Ftdi::from_backend`]: ../struct.Ftdi.html#method.from_backend
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use std::{cmp, fmt, thread};
+
+use crate::backend::{Backend, DeviceDescriptor, EndpointDescriptor, InterfaceDescriptor};
+use crate::prop::{self, ChipType, DeviceProps};
+use crate::{ControlReq, Error, ErrorKind, Result, REQ_READ, REQ_WRITE, VID_FTDI};
+
+/// Modem status reported in the first status byte (the lower nibble is always 1).
+const STATUS0_BASE: u8 = 0x01;
+const STATUS0_CTS: u8 = 1 << 4;
+const STATUS0_DSR: u8 = 1 << 5;
+const STATUS0_DCD: u8 = 1 << 7;
+/// Line status reported in the second status byte: THRE and TEMT (the simulated transmitter is
+/// always empty).
+const STATUS1_TX_EMPTY: u8 = 0x60;
+
+const STR_MANUFACTURER: u8 = 1;
+const STR_PRODUCT: u8 = 2;
+const STR_SERIAL: u8 = 3;
+
+/// A simulated FTDI device.
+///
+/// Cloning a `SimDevice` yields another handle to the same simulated device, which can be used to
+/// inspect its state while `rftdi` drives it.
+#[derive(Clone)]
+pub struct SimDevice {
+    props: &'static DeviceProps,
+    bcd_major: u16,
+    state: Arc<Mutex<SimState>>,
+}
+
+struct SimState {
+    connected: bool,
+    serial: String,
+    eeprom: Vec<u16>,
+    ports: Vec<SimPort>,
+}
+
+struct SimPort {
+    claimed: bool,
+    dtr: bool,
+    rts: bool,
+    latency_timer: u8,
+    bitmode: u8,
+    bitmode_mask: u8,
+    pins: u8,
+    /// Levels of the upper 8 pins, which are only accessible in MPSSE mode.
+    pins_high: u8,
+    /// Received MPSSE command Bytes that do not form a complete command yet.
+    mpsse: Vec<u8>,
+    mpsse_loopback: bool,
+    /// Data waiting to be read from the bulk IN endpoint.
+    rx: VecDeque<u8>,
+}
+
+impl SimPort {
+    fn new() -> Self {
+        Self {
+            claimed: false,
+            dtr: false,
+            rts: false,
+            latency_timer: 16,
+            bitmode: 0,
+            bitmode_mask: 0,
+            pins: 0xFF,
+            pins_high: 0xFF,
+            mpsse: Vec::new(),
+            mpsse_loopback: false,
+            rx: VecDeque::new(),
+        }
+    }
+
+    fn status(&self) -> [u8; 2] {
+        let mut status0 = STATUS0_BASE;
+        // The simulated device behaves as if a loopback plug was connected.
+        if self.rts {
+            status0 |= STATUS0_CTS;
+        }
+        if self.dtr {
+            status0 |= STATUS0_DSR | STATUS0_DCD;
+        }
+        [status0, STATUS1_TX_EMPTY]
+    }
+
+    /// Executes all complete MPSSE commands in `self.mpsse`.
+    fn run_mpsse(&mut self) {
+        while let Some(len) = mpsse_command_len(&self.mpsse) {
+            let command = self.mpsse.drain(..len).collect::<Vec<_>>();
+            let op = command[0];
+            match op {
+                // Data shifting commands.
+                _ if op & 0x80 == 0 && len > 1 => {
+                    let read = op & 0x20 != 0;
+                    let write = op & 0x10 != 0;
+                    // The data input idles at the level of ADBUS2, unless it is looped back.
+                    let idle = if self.pins & 0x04 != 0 { 0xFF } else { 0x00 };
+                    let (count, data) = if op & 0x40 != 0 || op & 0x02 != 0 {
+                        (1, &command[2..])
+                    } else {
+                        let count = usize::from(u16::from_le_bytes([command[1], command[2]])) + 1;
+                        (count, &command[3..])
+                    };
+                    if read {
+                        if write && self.mpsse_loopback {
+                            self.rx.extend(data);
+                        } else {
+                            self.rx.extend(std::iter::repeat_n(idle, count));
+                        }
+                    }
+                }
+                0x80 => self.pins = self.pins & !command[2] | command[1] & command[2],
+                0x82 => self.pins_high = self.pins_high & !command[2] | command[1] & command[2],
+                0x81 => self.rx.push_back(self.pins),
+                0x83 => self.rx.push_back(self.pins_high),
+                0x84 => self.mpsse_loopback = true,
+                0x85 => self.mpsse_loopback = false,
+                0x86..=0x8F | 0x96 | 0x97 | 0x9C..=0x9E => {
+                    // Clocking and timing commands have no visible effect.
+                }
+                _ => self.rx.extend(&[0xFA, op]),
+            }
+        }
+    }
+}
+
+/// Returns the length of the MPSSE command at the start of `buf`, or `None` if `buf` does not
+/// contain a complete command.
+fn mpsse_command_len(buf: &[u8]) -> Option<usize> {
+    let op = *buf.first()?;
+    let len = match op {
+        // TMS commands.
+        0x4A | 0x4B | 0x6A | 0x6B | 0x6E | 0x6F => 3,
+        // Bit-wise data commands.
+        _ if op & 0xC0 == 0 && op & 0x30 != 0 && op & 0x02 != 0 => {
+            if op & 0x10 != 0 {
+                3
+            } else {
+                2
+            }
+        }
+        // Byte-wise data commands.
+        _ if op & 0xC0 == 0 && op & 0x30 != 0 => {
+            let count = usize::from(u16::from_le_bytes([*buf.get(1)?, *buf.get(2)?])) + 1;
+            if op & 0x10 != 0 {
+                3 + count
+            } else {
+                3
+            }
+        }
+        0x80 | 0x82 | 0x86 | 0x8F | 0x9C | 0x9D | 0x9E => 3,
+        0x8E => 2,
+        // Everything else is a single Byte (including invalid commands).
+        _ => 1,
+    };
+
+    if buf.len() >= len {
+        Some(len)
+    } else {
+        None
+    }
+}
+
+impl SimDevice {
+    /// Creates a simulated device of the given model.
+    ///
+    /// `model` must be one of the names returned by [`SimDevice::models`], otherwise an error of
+    /// kind `UnsupportedDevice` is returned.
+    ///
+    /// [`SimDevice::models`]: #method.models
+    pub fn new(model: &str) -> Result<Self> {
+        let (bcd_major, props) = prop::DEVICES
+            .iter()
+            .enumerate()
+            .find_map(|(major, props)| match props {
+                Some(props) if props.model == model => Some((major as u16, props)),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::UnsupportedDevice,
+                    format!("cannot simulate unknown model '{}'", model),
+                )
+            })?;
+
+        let eeprom_words = match props.chip {
+            ChipType::Am | ChipType::Bm | ChipType::FT2232C | ChipType::R => 64,
+            ChipType::FT2232H | ChipType::FT4232H | ChipType::FT232H | ChipType::X => 128,
+        };
+
+        Ok(Self {
+            props,
+            bcd_major,
+            state: Arc::new(Mutex::new(SimState {
+                connected: true,
+                serial: String::from("SIM00001"),
+                eeprom: vec![0xFFFF; eeprom_words],
+                ports: props.ports.iter().map(|_| SimPort::new()).collect(),
+            })),
+        })
+    }
+
+    /// Returns the model names that can be simulated.
+    pub fn models() -> impl Iterator<Item = &'static str> {
+        prop::DEVICES.iter().flatten().map(|props| props.model)
+    }
+
+    /// Sets the serial number string reported by the device.
+    pub fn set_serial(&self, serial: &str) {
+        self.state().serial = serial.to_string();
+    }
+
+    /// Returns a copy of the simulated EEPROM contents.
+    pub fn eeprom(&self) -> Vec<u16> {
+        self.state().eeprom.clone()
+    }
+
+    /// Overwrites the simulated EEPROM with `words`.
+    ///
+    /// `words` must not be longer than the EEPROM, or this method will panic.
+    pub fn set_eeprom(&self, words: &[u16]) {
+        let mut state = self.state();
+        assert!(words.len() <= state.eeprom.len(), "EEPROM image too large");
+        state.eeprom[..words.len()].copy_from_slice(words);
+    }
+
+    /// Simulates unplugging the device.
+    ///
+    /// All further operations on the device will fail with an error of kind `Disconnected`.
+    pub fn unplug(&self) {
+        self.state().connected = false;
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Locks the device state, failing if the device was unplugged.
+    fn connected_state(&self) -> Result<MutexGuard<'_, SimState>> {
+        let state = self.state();
+        if state.connected {
+            Ok(state)
+        } else {
+            Err(Error::new(
+                ErrorKind::Disconnected,
+                "simulated device was unplugged",
+            ))
+        }
+    }
+
+    fn product_id(&self) -> u16 {
+        match self.props.chip {
+            ChipType::Am | ChipType::Bm | ChipType::R => 0x6001,
+            ChipType::FT2232C | ChipType::FT2232H => 0x6010,
+            ChipType::FT4232H => 0x6011,
+            ChipType::FT232H => 0x6014,
+            ChipType::X => 0x6015,
+        }
+    }
+
+    fn max_packet_size(&self) -> u16 {
+        if self.props.chip.is_h() {
+            512
+        } else {
+            64
+        }
+    }
+
+    /// Maps the `wIndex` of a control request to the addressed port.
+    fn port_index(&self, index: u16) -> Result<usize> {
+        if self.props.ports.len() == 1 {
+            // Single-port devices ignore the interface number (`SetBaudrate` also uses the lower
+            // byte for divisor bits).
+            return Ok(0);
+        }
+
+        match usize::from(index & 0xFF) {
+            0 => Ok(0),
+            n if n <= self.props.ports.len() => Ok(n - 1),
+            _ => Err(pipe_error()),
+        }
+    }
+
+    /// Maps a bulk endpoint address to the corresponding port.
+    fn endpoint_port(&self, endpoint: u8) -> Result<usize> {
+        let port = usize::from((endpoint & 0x7F).saturating_sub(1) / 2);
+        if port < self.props.ports.len() {
+            Ok(port)
+        } else {
+            Err(Error::new(ErrorKind::Usb, "invalid endpoint"))
+        }
+    }
+}
+
+fn pipe_error() -> Error {
+    Error::new(ErrorKind::Usb, "pipe error (request not supported)")
+}
+
+impl fmt::Debug for SimDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimDevice")
+            .field("model", &self.props.model)
+            .finish()
+    }
+}
+
+impl Backend for SimDevice {
+    fn device_descriptor(&self) -> DeviceDescriptor {
+        DeviceDescriptor {
+            vendor_id: VID_FTDI,
+            product_id: self.product_id(),
+            device_version: (self.bcd_major / 10) << 12 | (self.bcd_major % 10) << 8,
+            num_configurations: 1,
+            manufacturer_string_index: Some(STR_MANUFACTURER),
+            product_string_index: Some(STR_PRODUCT),
+            serial_number_string_index: Some(STR_SERIAL),
+        }
+    }
+
+    fn interfaces(&self) -> Result<Vec<InterfaceDescriptor>> {
+        let max_packet_size = self.max_packet_size();
+        let interfaces = (0..self.props.ports.len() as u8)
+            .map(|i| InterfaceDescriptor {
+                number: i,
+                num_alt_settings: 1,
+                endpoints: vec![
+                    EndpointDescriptor {
+                        address: 0x81 + 2 * i,
+                        max_packet_size,
+                    },
+                    EndpointDescriptor {
+                        address: 0x02 + 2 * i,
+                        max_packet_size,
+                    },
+                ],
+            })
+            .collect();
+        Ok(interfaces)
+    }
+
+    fn bus_number(&self) -> u8 {
+        0
+    }
+
+    fn address(&self) -> u8 {
+        1
+    }
+
+    fn read_string(&self, index: u8, _timeout: Duration) -> Result<String> {
+        let state = self.connected_state()?;
+        match index {
+            STR_MANUFACTURER => Ok(String::from("FTDI")),
+            STR_PRODUCT => Ok(format!("{} (simulated)", self.props.model)),
+            STR_SERIAL => Ok(state.serial.clone()),
+            _ => Err(pipe_error()),
+        }
+    }
+
+    fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        _value: u16,
+        index: u16,
+        buf: &mut [u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        if request_type != REQ_READ {
+            return Err(pipe_error());
+        }
+
+        let state = self.connected_state()?;
+        let data: Vec<u8> = if request == ControlReq::ReadEeprom as u8 {
+            let len = state.eeprom.len();
+            // Like real EEPROMs, addresses wrap around at the end.
+            state.eeprom[usize::from(index) % len]
+                .to_le_bytes()
+                .to_vec()
+        } else {
+            let port = &state.ports[self.port_index(index)?];
+            if request == ControlReq::PollModemStatus as u8 {
+                port.status().to_vec()
+            } else if request == ControlReq::GetLatencyTimer as u8 {
+                vec![port.latency_timer]
+            } else if request == ControlReq::ReadPins as u8 {
+                vec![port.pins]
+            } else {
+                return Err(pipe_error());
+            }
+        };
+
+        let n = cmp::min(buf.len(), data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        if request_type != REQ_WRITE {
+            return Err(pipe_error());
+        }
+
+        let mut state = self.connected_state()?;
+        if request == ControlReq::WriteEeprom as u8 {
+            let len = state.eeprom.len();
+            state.eeprom[usize::from(index) % len] = value;
+        } else if request == ControlReq::EraseEeprom as u8 {
+            state.eeprom.iter_mut().for_each(|word| *word = 0xFFFF);
+        } else {
+            let port = &mut state.ports[self.port_index(index)?];
+            let [lo, hi] = value.to_le_bytes();
+            if request == ControlReq::Reset as u8 {
+                if value & 1 != 0 {
+                    port.rx.clear();
+                }
+            } else if request == ControlReq::SetModemCtrl as u8 {
+                // The upper byte selects which lines to change, the lower byte has their values.
+                if hi & 1 != 0 {
+                    port.dtr = lo & 1 != 0;
+                }
+                if hi & 2 != 0 {
+                    port.rts = lo & 2 != 0;
+                }
+            } else if request == ControlReq::SetLatencyTimer as u8 {
+                port.latency_timer = lo;
+            } else if request == ControlReq::SetBitmode as u8 {
+                port.bitmode = hi;
+                port.bitmode_mask = lo;
+                port.mpsse.clear();
+                if hi == 0x20 {
+                    // CBUS bit-bang: the upper nibble selects outputs, the lower one their levels.
+                    let outputs = lo >> 4;
+                    port.pins = port.pins & !outputs | lo & outputs;
+                }
+            } else if request == ControlReq::SetFlowCtrl as u8
+                || request == ControlReq::SetBaudrate as u8
+                || request == ControlReq::SetData as u8
+                || request == ControlReq::SetEventChar as u8
+                || request == ControlReq::SetErrorChar as u8
+            {
+                // Line configuration has no effect on the loopback.
+            } else {
+                return Err(pipe_error());
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let port = self.endpoint_port(endpoint)?;
+        let packet_size = usize::from(self.max_packet_size());
+
+        // Without pending data, the device only responds when its latency timer expires.
+        let latency = {
+            let port = &self.connected_state()?.ports[port];
+            if port.rx.is_empty() {
+                Some(Duration::from_millis(port.latency_timer.into()))
+            } else {
+                None
+            }
+        };
+        if let Some(latency) = latency {
+            thread::sleep(cmp::min(latency, timeout));
+        }
+
+        let mut state = self.connected_state()?;
+        let port = &mut state.ports[port];
+
+        // Split the pending data into packets, each prefixed with the modem status. If there is
+        // no data, a status-only packet is sent, like the device does when its latency timer
+        // expires.
+        let mut n = 0;
+        while buf.len() - n >= 2 {
+            let len = cmp::min(cmp::min(packet_size, buf.len() - n) - 2, port.rx.len());
+            buf[n..n + 2].copy_from_slice(&port.status());
+            for (dest, byte) in buf[n + 2..n + 2 + len].iter_mut().zip(port.rx.drain(..len)) {
+                *dest = byte;
+            }
+            n += 2 + len;
+
+            // A short packet ends the transfer.
+            if 2 + len < packet_size {
+                break;
+            }
+        }
+
+        Ok(n)
+    }
+
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], _timeout: Duration) -> Result<usize> {
+        let port = self.endpoint_port(endpoint)?;
+        let mut state = self.connected_state()?;
+        let port = &mut state.ports[port];
+
+        match port.bitmode {
+            // Asynchronous bit-bang: every Byte drives the output pins.
+            0x01 => {
+                if let Some(&last) = buf.last() {
+                    port.pins = port.pins & !port.bitmode_mask | last & port.bitmode_mask;
+                }
+            }
+            // Synchronous bit-bang: the pins are sampled before each Byte is applied.
+            0x04 => {
+                for &byte in buf {
+                    port.rx.push_back(port.pins);
+                    port.pins = port.pins & !port.bitmode_mask | byte & port.bitmode_mask;
+                }
+            }
+            // MPSSE: the data is a stream of commands.
+            0x02 => {
+                port.mpsse.extend_from_slice(buf);
+                port.run_mpsse();
+            }
+            // Everything else is looped back.
+            _ => port.rx.extend(buf),
+        }
+
+        Ok(buf.len())
+    }
+
+    fn claim_interface(&self, interface: u8) -> Result<()> {
+        let mut state = self.connected_state()?;
+        let port = state
+            .ports
+            .get_mut(usize::from(interface))
+            .ok_or_else(|| Error::new(ErrorKind::Usb, "no such interface"))?;
+        if port.claimed {
+            return Err(Error::new(ErrorKind::Usb, "interface is busy"));
+        }
+        port.claimed = true;
+        Ok(())
+    }
+
+    fn release_interface(&self, interface: u8) -> Result<()> {
+        let mut state = self.state();
+        if let Some(port) = state.ports.get_mut(usize::from(interface)) {
+            port.claimed = false;
+        }
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<()> {
+        let mut state = self.connected_state()?;
+        for port in &mut state.ports {
+            *port = SimPort {
+                claimed: port.claimed,
+                ..SimPort::new()
+            };
+        }
+        Ok(())
+    }
+}
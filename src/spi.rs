@@ -0,0 +1,423 @@
+//! SPI master using the MPSSE.
+//!
+//! [`Spi`] drives the clock on ADBUS0, data out on ADBUS1 and samples data in on ADBUS2. Any of
+//! the remaining GPIOs can be used as chip selects.
+//!
+//! Every method of `Spi` that talks to a device costs one USB round trip. To perform several
+//! transfers in a single round trip, queue them in a [`Batch`]:
+//!
+//! ```no_run
+//! use rftdi::bitmode::Mpsse;
+//! use rftdi::spi::{ChipSelect, Mode, Spi};
+//! use rftdi::Ftdi;
+//!
+//! # fn main() -> rftdi::Result<()> {
+//! let ftdi = Ftdi::open_unique()?;
+//! let port = ftdi.open_port(0)?.into_mode::<Mpsse>()?;
+//! let mut spi = Spi::new(port, Mode::Mode0)?;
+//! spi.set_clock(10_000_000)?;
+//!
+//! let flash = ChipSelect::active_low(3);
+//! spi.add_chip_select(flash)?;
+//!
+//! let mut batch = spi.batch();
+//! batch.select(flash).write(&[0x9F]);
+//! let id = batch.read(3);
+//! batch.deselect(flash).select(flash).write(&[0x05]);
+//! let status = batch.read(1);
+//! batch.deselect(flash);
+//!
+//! let responses = spi.execute(&batch)?;
+//! println!("JEDEC ID: {:02x?}", &responses[id]);
+//! println!("Status: {:02x}", responses[status][0]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Spi`]: struct.Spi.html
+//! [`Batch`]: struct.Batch.html
+
+use std::fmt;
+
+use crate::bitmode::Mpsse;
+use crate::mpsse::{BitOrder, Commands, Edge, Response, Responses};
+use crate::{Port, Result};
+
+/// Pins used by the SPI bus itself (clock, data out, data in).
+const BUS_PINS: u8 = 3;
+const PIN_SCK: u8 = 0x01;
+const PIN_MOSI: u8 = 0x02;
+
+/// SPI clock polarity and phase.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Mode {
+    /// The clock idles low, data is sampled on the rising edge.
+    Mode0,
+    /// The clock idles low, data is sampled on the falling edge.
+    Mode1,
+    /// The clock idles high, data is sampled on the falling edge.
+    Mode2,
+    /// The clock idles high, data is sampled on the rising edge.
+    Mode3,
+}
+
+impl Mode {
+    fn idle_high(self) -> bool {
+        matches!(self, Mode::Mode2 | Mode::Mode3)
+    }
+
+    /// Returns the clock edges on which data is written and read.
+    fn edges(self) -> (Edge, Edge) {
+        match self {
+            Mode::Mode0 | Mode::Mode3 => (Edge::Falling, Edge::Rising),
+            Mode::Mode1 | Mode::Mode2 => (Edge::Rising, Edge::Falling),
+        }
+    }
+}
+
+/// A chip select pin.
+///
+/// Pins are numbered 0-15, where 0-7 are the lower GPIO pins (ADBUS0-ADBUS7) and 8-15 are the
+/// upper GPIO pins (ACBUS0-ACBUS7). Pins 0-2 are used by the SPI bus and cannot be chip selects.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ChipSelect {
+    pin: u8,
+    active_high: bool,
+}
+
+impl ChipSelect {
+    /// Creates a chip select that is driven low to select the device.
+    pub fn active_low(pin: u8) -> Self {
+        Self {
+            pin,
+            active_high: false,
+        }
+    }
+
+    /// Creates a chip select that is driven high to select the device.
+    pub fn active_high(pin: u8) -> Self {
+        Self {
+            pin,
+            active_high: true,
+        }
+    }
+
+    /// Returns the pin number.
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Returns whether the device is selected by driving the pin high.
+    pub fn is_active_high(&self) -> bool {
+        self.active_high
+    }
+}
+
+/// Level and direction of the 16 GPIO pins.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Gpio {
+    value: u16,
+    direction: u16,
+}
+
+impl Gpio {
+    /// Drives `cs` to its active or inactive level, and queues the corresponding GPIO command.
+    fn drive(&mut self, commands: &mut Commands, cs: ChipSelect, active: bool) {
+        let mask = 1 << cs.pin;
+        if active == cs.active_high {
+            self.value |= mask;
+        } else {
+            self.value &= !mask;
+        }
+        self.direction |= mask;
+
+        let [value_low, value_high] = self.value.to_le_bytes();
+        let [direction_low, direction_high] = self.direction.to_le_bytes();
+        if cs.pin < 8 {
+            commands.set_gpio_low(value_low, direction_low);
+        } else {
+            commands.set_gpio_high(value_high, direction_high);
+        }
+    }
+}
+
+/// An SPI master.
+///
+/// Created from a port in MPSSE mode with [`Spi::new`].
+///
+/// [`Spi::new`]: #method.new
+pub struct Spi {
+    port: Port<Mpsse>,
+    mode: Mode,
+    bit_order: BitOrder,
+    gpio: Gpio,
+    chip_selects: Vec<ChipSelect>,
+}
+
+impl Spi {
+    /// Creates an SPI master using `port`, and configures the bus pins for `mode`.
+    ///
+    /// Data is transferred MSB first, which can be changed with [`set_bit_order`]. The clock
+    /// frequency is left unchanged, use [`set_clock`] to configure it.
+    ///
+    /// [`set_bit_order`]: #method.set_bit_order
+    /// [`set_clock`]: #method.set_clock
+    pub fn new(mut port: Port<Mpsse>, mode: Mode) -> Result<Self> {
+        let mut commands = Commands::new();
+        commands.loopback(false);
+        if port.mpsse_is_h() {
+            commands
+                .three_phase_clocking(false)
+                .adaptive_clocking(false);
+        }
+        port.execute(&commands)?;
+
+        let mut this = Self {
+            port,
+            mode,
+            bit_order: BitOrder::MsbFirst,
+            gpio: Gpio {
+                value: 0,
+                direction: u16::from(PIN_SCK | PIN_MOSI),
+            },
+            chip_selects: Vec::new(),
+        };
+        this.set_mode(mode)?;
+        Ok(this)
+    }
+
+    /// Returns the configured SPI mode.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Changes the SPI mode.
+    ///
+    /// This immediately drives the clock to its new idle level.
+    pub fn set_mode(&mut self, mode: Mode) -> Result<()> {
+        self.mode = mode;
+        if mode.idle_high() {
+            self.gpio.value |= u16::from(PIN_SCK);
+        } else {
+            self.gpio.value &= !u16::from(PIN_SCK);
+        }
+
+        let [value, _] = self.gpio.value.to_le_bytes();
+        let [direction, _] = self.gpio.direction.to_le_bytes();
+        self.port
+            .execute(Commands::new().set_gpio_low(value, direction))?;
+        Ok(())
+    }
+
+    /// Returns the configured bit order.
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    /// Sets the order in which the bits of each Byte are transferred.
+    pub fn set_bit_order(&mut self, bit_order: BitOrder) {
+        self.bit_order = bit_order;
+    }
+
+    /// Sets the SPI clock frequency, returning the frequency actually achieved.
+    ///
+    /// See [`Port::set_clock`] for details.
+    ///
+    /// [`Port::set_clock`]: ../struct.Port.html#method.set_clock
+    pub fn set_clock(&mut self, hz: u32) -> Result<u32> {
+        self.port.set_clock(hz)
+    }
+
+    /// Configures `cs` as a chip select output and drives it to its inactive level.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pin is used by the SPI bus or out of range, or if it is already used by
+    /// another chip select.
+    pub fn add_chip_select(&mut self, cs: ChipSelect) -> Result<()> {
+        assert!(
+            (BUS_PINS..16).contains(&cs.pin),
+            "chip select pin must be between {} and 15, got {}",
+            BUS_PINS,
+            cs.pin
+        );
+        assert!(
+            self.chip_selects.iter().all(|other| other.pin != cs.pin),
+            "pin {} is already used as a chip select",
+            cs.pin
+        );
+
+        let mut commands = Commands::new();
+        let mut gpio = self.gpio;
+        gpio.drive(&mut commands, cs, false);
+        self.port.execute(&commands)?;
+        self.gpio = gpio;
+        self.chip_selects.push(cs);
+        Ok(())
+    }
+
+    /// Creates a new, empty batch of transfers.
+    ///
+    /// The batch uses the current mode and bit order, and can be executed with [`execute`].
+    ///
+    /// [`execute`]: #method.execute
+    pub fn batch(&self) -> Batch {
+        let (write_edge, read_edge) = self.mode.edges();
+        Batch {
+            commands: Commands::new(),
+            write_edge,
+            read_edge,
+            bit_order: self.bit_order,
+            gpio: self.gpio,
+            chip_selects: self.chip_selects.clone(),
+        }
+    }
+
+    /// Executes a batch of transfers in a single USB round trip.
+    pub fn execute(&mut self, batch: &Batch) -> Result<Responses> {
+        let responses = self.port.execute(&batch.commands)?;
+        self.gpio = batch.gpio;
+        Ok(responses)
+    }
+
+    /// Selects the device at `cs`, transfers `data` full-duplex, and deselects the device.
+    ///
+    /// The received data is written back to `data`.
+    pub fn transfer(&mut self, cs: ChipSelect, data: &mut [u8]) -> Result<()> {
+        let mut batch = self.batch();
+        batch.select(cs);
+        let response = batch.transfer(data);
+        batch.deselect(cs);
+
+        let responses = self.execute(&batch)?;
+        data.copy_from_slice(&responses[response]);
+        Ok(())
+    }
+
+    /// Selects the device at `cs`, writes `data`, and deselects the device.
+    pub fn write(&mut self, cs: ChipSelect, data: &[u8]) -> Result<()> {
+        self.write_read(cs, data, &mut [])
+    }
+
+    /// Selects the device at `cs`, reads into `buf`, and deselects the device.
+    pub fn read(&mut self, cs: ChipSelect, buf: &mut [u8]) -> Result<()> {
+        self.write_read(cs, &[], buf)
+    }
+
+    /// Selects the device at `cs`, writes `data`, then reads into `buf`, and deselects the device.
+    ///
+    /// This is the typical access pattern of SPI memories, which receive a command and then
+    /// respond with data.
+    pub fn write_read(&mut self, cs: ChipSelect, data: &[u8], buf: &mut [u8]) -> Result<()> {
+        let mut batch = self.batch();
+        batch.select(cs).write(data);
+        let response = batch.read(buf.len());
+        batch.deselect(cs);
+
+        let responses = self.execute(&batch)?;
+        buf.copy_from_slice(&responses[response]);
+        Ok(())
+    }
+
+    /// Returns the underlying port.
+    ///
+    /// The GPIO pins used by `self` should not be modified through the port.
+    pub fn into_inner(self) -> Port<Mpsse> {
+        self.port
+    }
+}
+
+impl fmt::Debug for Spi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spi")
+            .field("port", &self.port)
+            .field("mode", &self.mode)
+            .field("bit_order", &self.bit_order)
+            .field("chip_selects", &self.chip_selects)
+            .finish()
+    }
+}
+
+/// A batch of SPI transfers, executed in a single USB round trip.
+///
+/// Created by [`Spi::batch`]. Transfers that read data return a [`Response`] handle, which is
+/// used to look up the received data in the [`Responses`] returned by [`Spi::execute`].
+///
+/// [`Spi::batch`]: struct.Spi.html#method.batch
+/// [`Spi::execute`]: struct.Spi.html#method.execute
+/// [`Response`]: ../mpsse/struct.Response.html
+/// [`Responses`]: ../mpsse/struct.Responses.html
+#[derive(Debug, Clone)]
+pub struct Batch {
+    commands: Commands,
+    write_edge: Edge,
+    read_edge: Edge,
+    bit_order: BitOrder,
+    /// GPIO state at the end of the batch.
+    gpio: Gpio,
+    chip_selects: Vec<ChipSelect>,
+}
+
+impl Batch {
+    fn check_chip_select(&self, cs: ChipSelect) {
+        assert!(
+            self.chip_selects.contains(&cs),
+            "chip select {:?} was not added with `Spi::add_chip_select`",
+            cs
+        );
+    }
+
+    /// Selects the device at `cs` by driving the pin to its active level.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cs` was not added with [`Spi::add_chip_select`].
+    ///
+    /// [`Spi::add_chip_select`]: struct.Spi.html#method.add_chip_select
+    pub fn select(&mut self, cs: ChipSelect) -> &mut Self {
+        self.check_chip_select(cs);
+        self.gpio.drive(&mut self.commands, cs, true);
+        self
+    }
+
+    /// Deselects the device at `cs` by driving the pin to its inactive level.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cs` was not added with [`Spi::add_chip_select`].
+    ///
+    /// [`Spi::add_chip_select`]: struct.Spi.html#method.add_chip_select
+    pub fn deselect(&mut self, cs: ChipSelect) -> &mut Self {
+        self.check_chip_select(cs);
+        self.gpio.drive(&mut self.commands, cs, false);
+        self
+    }
+
+    /// Writes `data` while simultaneously reading the same number of Bytes.
+    pub fn transfer(&mut self, data: &[u8]) -> Response {
+        self.commands
+            .transfer_bytes(self.write_edge, self.read_edge, self.bit_order, data)
+    }
+
+    /// Writes `data`, ignoring the received data.
+    pub fn write(&mut self, data: &[u8]) -> &mut Self {
+        self.commands
+            .write_bytes(self.write_edge, self.bit_order, data);
+        self
+    }
+
+    /// Reads `len` Bytes.
+    ///
+    /// While reading, the data output is held at its previous level.
+    pub fn read(&mut self, len: usize) -> Response {
+        self.commands
+            .read_bytes(self.read_edge, self.bit_order, len)
+    }
+
+    /// Returns the underlying MPSSE commands, to queue commands without a dedicated method.
+    ///
+    /// The GPIO pins used for the SPI bus and the chip selects should not be modified this way.
+    pub fn commands(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+}
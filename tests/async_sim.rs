@@ -0,0 +1,69 @@
+//! Tests for `AsyncPort`, running against the simulated device.
+
+#![cfg(all(feature = "sim", feature = "async"))]
+
+use futures_io::{AsyncRead, AsyncWrite};
+use rftdi::sim::SimDevice;
+use rftdi::{Ftdi, ModemStatus};
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = Box::pin(fut);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(out) => return out,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn async_loopback() {
+    let ftdi = Ftdi::from_backend(SimDevice::new("FT2232H").unwrap()).unwrap();
+    let mut port = ftdi.open_port(1).unwrap().into_async().unwrap();
+
+    block_on(async {
+        port.set_rts(true).await.unwrap();
+        let status = port.poll_modem_status().await.unwrap();
+        assert!(status.contains(ModemStatus::CTS));
+
+        let data = (0..10_000).map(|i| i as u8).collect::<Vec<_>>();
+        let mut written = 0;
+        while written < data.len() {
+            written += poll_fn(|cx| Pin::new(&mut port).poll_write(cx, &data[written..]))
+                .await
+                .unwrap();
+        }
+        poll_fn(|cx| Pin::new(&mut port).poll_flush(cx))
+            .await
+            .unwrap();
+
+        let mut received = Vec::new();
+        let mut buf = [0; 1000];
+        while received.len() < data.len() {
+            let n = poll_fn(|cx| Pin::new(&mut port).poll_read(cx, &mut buf))
+                .await
+                .unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(received, data);
+    });
+
+    drop(port);
+    // The interface must be released when the `AsyncPort` is dropped.
+    ftdi.open_port(1).unwrap();
+}
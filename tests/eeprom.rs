@@ -0,0 +1,242 @@
+//! Tests for EEPROM image decoding and encoding.
+
+use rftdi::eeprom::{self, DriveStrength, Driver, EepromConfig, Ft232hCbus, Ft232rCbus, FtxCbus};
+use rftdi::ErrorKind;
+
+/// Appends a USB string descriptor to `image` and stores its pointer and length at `addr`.
+fn push_string(image: &mut Vec<u8>, addr: usize, s: &str) {
+    let len = 2 + 2 * s.len();
+    image[addr] = image.len() as u8 | 0x80;
+    image[addr + 1] = len as u8;
+    image.push(len as u8);
+    image.push(0x03);
+    for c in s.encode_utf16() {
+        image.extend_from_slice(&c.to_le_bytes());
+    }
+}
+
+fn to_words(mut image: Vec<u8>, words: usize) -> Vec<u16> {
+    image.resize(words * 2, 0);
+    image
+        .chunks(2)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]))
+        .collect()
+}
+
+/// An FT232R image, as programmed by FTDI.
+fn ft232r_image() -> Vec<u16> {
+    let mut image = vec![
+        0x00, 0x40, 0x03, 0x04, 0x01, 0x60, 0x00, 0x06, 0xA0, 0x2D, 0x08, 0x00, 0x00, 0x00, 0, 0,
+        0, 0, 0, 0, 0x23, 0x10, 0x05, 0x00,
+    ];
+    push_string(&mut image, 0x0E, "FTDI");
+    push_string(&mut image, 0x10, "FT232R USB UART");
+    push_string(&mut image, 0x12, "A1234567");
+    let mut words = to_words(image, 64);
+    eeprom::update_checksum("FT232R", &mut words).unwrap();
+    words
+}
+
+#[test]
+fn decode_ft232r() {
+    let config = EepromConfig::decode("FT232R", &ft232r_image()).unwrap();
+    assert_eq!(config.model(), "FT232R");
+    assert_eq!(config.vendor_id, 0x0403);
+    assert_eq!(config.product_id, 0x6001);
+    assert_eq!(config.manufacturer, "FTDI");
+    assert_eq!(config.product, "FT232R USB UART");
+    assert_eq!(config.serial.as_deref(), Some("A1234567"));
+    assert!(!config.self_powered);
+    assert!(config.remote_wakeup);
+    assert_eq!(config.max_current_ma, 90);
+    assert_eq!(config.ports.len(), 1);
+    assert_eq!(config.ports[0].driver, Driver::D2xx);
+    assert!(!config.ports[0].high_current);
+    assert!(config.pin_groups.is_empty());
+
+    // Encoding an unmodified config reproduces the image.
+    assert_eq!(config.encode().unwrap(), ft232r_image());
+}
+
+#[test]
+fn modify_ft232r() {
+    let mut config = EepromConfig::decode("FT232R", &ft232r_image()).unwrap();
+    config.product = String::from("Test fixture");
+    config.serial = None;
+    config.self_powered = true;
+    config.max_current_ma = 500;
+    config.ports[0].driver = Driver::Vcp;
+    config.ports[0].high_current = true;
+
+    let image = config.encode().unwrap();
+    let decoded = EepromConfig::decode("FT232R", &image).unwrap();
+    assert_eq!(decoded.manufacturer, "FTDI");
+    assert_eq!(decoded.product, "Test fixture");
+    assert_eq!(decoded.serial, None);
+    assert!(decoded.self_powered);
+    assert_eq!(decoded.max_current_ma, 500);
+    assert_eq!(decoded.ports, config.ports);
+
+    // CBUS configuration is carried over.
+    assert_eq!(image[0x0A], ft232r_image()[0x0A]);
+    assert_eq!(image[0x0B], ft232r_image()[0x0B]);
+}
+
+#[test]
+fn ft2232h_pin_groups() {
+    let mut image = vec![0; 0x1A];
+    image[0x00] = 0x08;
+    image[0x0C] = 0x9A;
+    push_string(&mut image, 0x0E, "FTDI");
+    push_string(&mut image, 0x10, "Dual RS232-HS");
+    let config = EepromConfig::decode("FT2232H", &to_words(image, 64)).unwrap();
+
+    assert_eq!(config.ports[0].driver, Driver::Vcp);
+    assert_eq!(config.ports[1].driver, Driver::D2xx);
+    assert_eq!(config.pin_groups[0].drive, DriveStrength::Ma12);
+    assert!(config.pin_groups[0].schmitt);
+    assert!(!config.pin_groups[0].slow_slew);
+    assert_eq!(config.pin_groups[1].drive, DriveStrength::Ma8);
+    assert!(config.pin_groups[1].schmitt);
+    assert_eq!(config.serial, None);
+}
+
+#[test]
+fn cbus() {
+    let mut config = EepromConfig::decode("FT232R", &ft232r_image()).unwrap();
+    let functions = (0..5)
+        .map(|pin| config.cbus(pin).unwrap())
+        .collect::<Vec<Ft232rCbus>>();
+    assert_eq!(
+        functions,
+        [
+            Ft232rCbus::TxLed,
+            Ft232rCbus::RxLed,
+            Ft232rCbus::TxDen,
+            Ft232rCbus::PwrEn,
+            Ft232rCbus::Sleep,
+        ]
+    );
+
+    config.set_cbus(1, Ft232rCbus::IoMode).unwrap();
+    config.set_cbus(4, Ft232rCbus::Clk12).unwrap();
+    let image = config.encode().unwrap();
+    assert_eq!(image[0x0A], 0x10A3);
+    assert_eq!(image[0x0B], 0x0008);
+    eeprom::verify_checksum("FT232R", &image).unwrap();
+    let decoded = EepromConfig::decode("FT232R", &image).unwrap();
+    assert_eq!(decoded.cbus::<Ft232rCbus>(0).unwrap(), Ft232rCbus::TxLed);
+    assert_eq!(decoded.cbus::<Ft232rCbus>(1).unwrap(), Ft232rCbus::IoMode);
+    assert_eq!(decoded.cbus::<Ft232rCbus>(4).unwrap(), Ft232rCbus::Clk12);
+
+    // CBUS4 has no I/O mode, and there is no CBUS5.
+    let err = config.set_cbus(4, Ft232rCbus::IoMode).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Eeprom));
+    assert!(config.cbus::<Ft232rCbus>(5).is_err());
+    // Functions of other chips are rejected.
+    assert!(config.cbus::<FtxCbus>(0).is_err());
+    assert!(config.set_cbus(0, Ft232hCbus::Drive1).is_err());
+    assert_eq!(config.encode().unwrap(), image);
+}
+
+#[test]
+fn cbus_ft232h_ftx() {
+    let mut image = vec![0; 0xA0];
+    push_string(&mut image, 0x0E, "FTDI");
+    push_string(&mut image, 0x10, "Single RS232-HS");
+    let mut config = EepromConfig::decode("FT232H", &to_words(image, 128)).unwrap();
+    config.set_cbus(9, Ft232hCbus::IoMode).unwrap();
+    config.set_cbus(8, Ft232hCbus::Clk7_5).unwrap();
+    assert!(config.set_cbus(7, Ft232hCbus::IoMode).is_err());
+    assert!(config.set_cbus(10, Ft232hCbus::Drive0).is_err());
+    assert_eq!(config.encode().unwrap()[0x0E], 0x008C);
+    assert_eq!(config.cbus::<Ft232hCbus>(9).unwrap(), Ft232hCbus::IoMode);
+
+    let mut image = vec![0; 0xA0];
+    image[0x1C] = 0x30;
+    push_string(&mut image, 0x0E, "FTDI");
+    push_string(&mut image, 0x10, "FT230X Basic UART");
+    let mut config = EepromConfig::decode("FT-X", &to_words(image, 128)).unwrap();
+    assert!(config.cbus::<FtxCbus>(2).is_err());
+    config.set_cbus(2, FtxCbus::VbusSense).unwrap();
+    config.set_cbus(3, FtxCbus::KeepAwake).unwrap();
+    assert_eq!(config.cbus::<FtxCbus>(0).unwrap(), FtxCbus::Tristate);
+    assert_eq!(config.encode().unwrap()[0x0E], 0x1511);
+}
+
+#[test]
+fn strings_on_large_eeprom() {
+    // A 93C66 holds 256 words, but string pointers can only address the first 256 Bytes.
+    let mut image = vec![0; 0x9A];
+    image[0x0C] = 0x9A;
+    push_string(&mut image, 0x0E, "FTDI");
+    push_string(&mut image, 0x10, "Dual RS232-HS");
+    let mut config = EepromConfig::decode("FT2232H", &to_words(image, 256)).unwrap();
+
+    config.manufacturer = "Future Technology".to_string();
+    config.product = "Dual RS232-HS Adapter".to_string();
+    config.serial = Some("FT123456".to_string());
+    let image = config.encode().unwrap();
+    let decoded = EepromConfig::decode("FT2232H", &image).unwrap();
+    assert_eq!(decoded.manufacturer, config.manufacturer);
+    assert_eq!(decoded.product, config.product);
+    assert_eq!(decoded.serial, config.serial);
+    for &addr in &[0x07, 0x08, 0x09] {
+        let (ptr, len) = (image[addr] & 0xFF, image[addr] >> 8);
+        assert!(ptr + len <= 0x100);
+    }
+
+    // Strings that would only fit behind the first 256 Bytes are rejected.
+    config.product = "x".repeat(60);
+    assert!(matches!(
+        config.encode().unwrap_err().kind(),
+        ErrorKind::Eeprom
+    ));
+}
+
+#[test]
+fn invalid() {
+    assert!(matches!(
+        EepromConfig::decode("FT232R", &[0xFFFF; 64])
+            .unwrap_err()
+            .kind(),
+        ErrorKind::Eeprom
+    ));
+    assert!(matches!(
+        EepromConfig::decode("FT232R", &[0; 63]).unwrap_err().kind(),
+        ErrorKind::Eeprom
+    ));
+
+    let mut config = EepromConfig::decode("FT232R", &ft232r_image()).unwrap();
+    config.product = "x".repeat(64);
+    assert!(matches!(
+        config.encode().unwrap_err().kind(),
+        ErrorKind::Eeprom
+    ));
+
+    let mut config = EepromConfig::decode("FT232R", &ft232r_image()).unwrap();
+    config.ports.clear();
+    assert!(matches!(
+        config.encode().unwrap_err().kind(),
+        ErrorKind::Eeprom
+    ));
+}
+
+#[test]
+fn checksum() {
+    let mut image = ft232r_image();
+    eeprom::verify_checksum("FT232R", &image).unwrap();
+    image[0x02] = 0x6015;
+    let err = eeprom::verify_checksum("FT232R", &image).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Eeprom));
+
+    // The FT-X user area is not covered by the checksum, which is always stored at word 0x7F.
+    let mut image = vec![0; 256];
+    eeprom::update_checksum("FT-X", &mut image).unwrap();
+    assert_ne!(image[0x7F], 0);
+    assert_eq!(image[0xFF], 0);
+    image[0x20] = 0x1234;
+    eeprom::verify_checksum("FT-X", &image).unwrap();
+    image[0x50] = 0x1234;
+    assert!(eeprom::verify_checksum("FT-X", &image).is_err());
+}
@@ -0,0 +1,97 @@
+//! Tests for MPSSE command encoding.
+
+use rftdi::mpsse::{BitOrder, Commands, Edge};
+
+#[test]
+fn encode() {
+    let mut commands = Commands::new();
+    commands
+        .set_gpio_low(0x08, 0x0B)
+        .clock_divisor(5)
+        .write_bytes(Edge::Falling, BitOrder::MsbFirst, &[0xAB, 0xCD]);
+    let a = commands.transfer_bytes(Edge::Falling, Edge::Rising, BitOrder::LsbFirst, &[0x01]);
+    let b = commands.read_bits(Edge::Falling, BitOrder::MsbFirst, 3);
+    let c = commands.get_gpio_high();
+    commands
+        .write_bits(Edge::Rising, BitOrder::MsbFirst, 0x80, 1)
+        .loopback(false)
+        .wait_on_io(true)
+        .send_immediate();
+
+    assert_eq!(
+        commands.as_bytes(),
+        &[
+            0x80, 0x08, 0x0B, // GPIO
+            0x86, 0x05, 0x00, // divisor
+            0x11, 0x01, 0x00, 0xAB, 0xCD, // write bytes
+            0x39, 0x00, 0x00, 0x01, // transfer bytes
+            0x26, 0x02, // read bits
+            0x83, // GPIO
+            0x12, 0x00, 0x80, // write bits
+            0x85, 0x88, 0x87,
+        ][..]
+    );
+    assert_eq!(commands.response_len(), 3);
+    assert_eq!((a.len(), b.len(), c.len()), (1, 1, 1));
+    assert_ne!(a, b);
+
+    commands.clear();
+    assert!(commands.is_empty());
+    assert_eq!(commands.response_len(), 0);
+}
+
+#[test]
+fn clocking() {
+    let mut commands = Commands::new();
+    commands
+        .divide_by_5(false)
+        .three_phase_clocking(true)
+        .adaptive_clocking(false)
+        .clock_divisor(0x1234);
+    assert_eq!(
+        commands.as_bytes(),
+        &[0x8A, 0x8C, 0x97, 0x86, 0x34, 0x12][..]
+    );
+    assert_eq!(commands.response_len(), 0);
+}
+
+#[test]
+fn split_long_transfers() {
+    let mut commands = Commands::new();
+    commands.write_bytes(Edge::Falling, BitOrder::MsbFirst, &[0xAB; 70000]);
+    let bytes = commands.as_bytes();
+    assert_eq!(bytes.len(), 3 + 65536 + 3 + 4464);
+    assert_eq!(bytes[..3], [0x11, 0xFF, 0xFF]);
+    assert_eq!(bytes[65539..65542], [0x11, 0x6F, 0x11]);
+    assert_eq!(commands.response_len(), 0);
+
+    // Reads are split into commands whose response fits into the device's buffer.
+    let mut commands = Commands::new();
+    let response = commands.read_bytes(Edge::Rising, BitOrder::MsbFirst, 1000);
+    assert_eq!(response.len(), 1000);
+    assert_eq!(commands.response_len(), 1000);
+    assert_eq!(
+        commands.as_bytes(),
+        &[0x20, 0xFF, 0x00, 0x20, 0xFF, 0x00, 0x20, 0xFF, 0x00, 0x20, 0xE7, 0x00][..]
+    );
+
+    let data = (0..600).map(|i| i as u8).collect::<Vec<_>>();
+    let mut commands = Commands::new();
+    commands.transfer_bytes(Edge::Falling, Edge::Rising, BitOrder::MsbFirst, &data);
+    let bytes = commands.as_bytes();
+    assert_eq!(bytes.len(), 3 * 3 + 600);
+    assert_eq!(bytes[..3], [0x31, 0xFF, 0x00]);
+    assert_eq!(bytes[259..262], [0x31, 0xFF, 0x00]);
+    assert_eq!(bytes[518..521], [0x31, 0x57, 0x00]);
+    assert_eq!(bytes[521..], data[512..]);
+
+    let empty = commands.read_bytes(Edge::Rising, BitOrder::MsbFirst, 0);
+    assert!(empty.is_empty());
+    assert_eq!(commands.as_bytes().len(), 609);
+}
+
+#[test]
+#[should_panic]
+fn bit_count_out_of_range() {
+    Commands::new().write_bits(Edge::Rising, BitOrder::MsbFirst, 0, 9);
+}
@@ -0,0 +1,726 @@
+//! Tests running against the simulated device.
+
+#![cfg(feature = "sim")]
+
+use rftdi::backend::{Backend, DeviceDescriptor, InterfaceDescriptor};
+use rftdi::sim::SimDevice;
+use rftdi::{Ftdi, ModemStatus};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn open(model: &str) -> (Ftdi, SimDevice) {
+    let sim = SimDevice::new(model).unwrap();
+    let ftdi = Ftdi::from_backend(sim.clone()).unwrap();
+    (ftdi, sim)
+}
+
+#[test]
+fn open_all_models() {
+    for model in SimDevice::models() {
+        let (ftdi, _) = open(model);
+        assert_eq!(ftdi.model(), model);
+        assert_eq!(ftdi.serial().unwrap(), "SIM00001");
+
+        for index in 0..ftdi.num_ports() {
+            let mut port = ftdi.open_port(index).unwrap();
+            port.set_latency_timer(20).unwrap();
+            assert_eq!(port.read_latency_timer().unwrap(), 20);
+            port.set_baud_rate(115_200).unwrap();
+        }
+    }
+}
+
+#[test]
+fn unknown_model() {
+    assert!(SimDevice::new("FT9000").is_err());
+}
+
+#[test]
+fn port_claimed_once() {
+    let (ftdi, _) = open("FT2232H");
+    let _port = ftdi.open_port(1).unwrap();
+    assert!(ftdi.open_port(1).is_err());
+    ftdi.open_port(0).unwrap();
+}
+
+#[test]
+fn modem_control() {
+    let (ftdi, _) = open("FT232R");
+    let mut port = ftdi.open_port(0).unwrap();
+    port.set_rts(true).unwrap();
+    port.set_dtr(false).unwrap();
+    let status = port.poll_modem_status().unwrap();
+    assert!(status.contains(ModemStatus::CTS));
+    assert!(!status.contains(ModemStatus::DSR));
+}
+
+#[test]
+fn loopback() {
+    for model in SimDevice::models() {
+        let (ftdi, _) = open(model);
+        let mut port = ftdi.open_port(0).unwrap();
+        port.set_timeout(Duration::from_millis(50));
+
+        let data = (0..5000).map(|i| i as u8).collect::<Vec<_>>();
+        port.write_all(&data).unwrap();
+
+        let mut received = Vec::new();
+        let mut buf = [0; 300];
+        while received.len() < data.len() {
+            let n = port.read(&mut buf).unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(received, data, "{}", model);
+        assert!(port.last_modem_status().contains(ModemStatus::TEMT));
+        assert!(port.take_line_errors().is_empty());
+    }
+}
+
+/// A backend wrapping a simulated device, which records all OUT control requests and stops
+/// accepting bulk OUT data after `accept` Bytes.
+struct TestBackend {
+    sim: SimDevice,
+    accept: Mutex<usize>,
+    /// `(request, value, index)` of every OUT control request.
+    controls: Arc<Mutex<Vec<(u8, u16, u16)>>>,
+}
+
+impl TestBackend {
+    fn new(model: &str) -> Self {
+        Self {
+            sim: SimDevice::new(model).unwrap(),
+            accept: Mutex::new(usize::MAX),
+            controls: Arc::default(),
+        }
+    }
+}
+
+impl Backend for TestBackend {
+    fn device_descriptor(&self) -> DeviceDescriptor {
+        self.sim.device_descriptor()
+    }
+    fn interfaces(&self) -> rftdi::Result<Vec<InterfaceDescriptor>> {
+        self.sim.interfaces()
+    }
+    fn bus_number(&self) -> u8 {
+        self.sim.bus_number()
+    }
+    fn address(&self) -> u8 {
+        self.sim.address()
+    }
+    fn read_string(&self, index: u8, timeout: Duration) -> rftdi::Result<String> {
+        self.sim.read_string(index, timeout)
+    }
+    fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> rftdi::Result<usize> {
+        self.sim
+            .read_control(request_type, request, value, index, buf, timeout)
+    }
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> rftdi::Result<usize> {
+        self.controls.lock().unwrap().push((request, value, index));
+        self.sim
+            .write_control(request_type, request, value, index, buf, timeout)
+    }
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> rftdi::Result<usize> {
+        self.sim.read_bulk(endpoint, buf, timeout)
+    }
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> rftdi::Result<usize> {
+        let mut accept = self.accept.lock().unwrap();
+        let n = buf.len().min(*accept);
+        *accept -= n;
+        self.sim.write_bulk(endpoint, &buf[..n], timeout)
+    }
+    fn claim_interface(&self, interface: u8) -> rftdi::Result<()> {
+        self.sim.claim_interface(interface)
+    }
+    fn release_interface(&self, interface: u8) -> rftdi::Result<()> {
+        self.sim.release_interface(interface)
+    }
+    fn reset(&self) -> rftdi::Result<()> {
+        self.sim.reset()
+    }
+}
+
+#[test]
+fn write_all_stalled() {
+    let backend = TestBackend::new("FT232R");
+    *backend.accept.lock().unwrap() = 100;
+    let ftdi = Ftdi::from_backend(backend).unwrap();
+    let mut port = ftdi.open_port(0).unwrap();
+
+    let err = port.write_all(&[0; 300]).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::Timeout));
+    assert_eq!(err.bytes_written(), Some(100));
+}
+
+#[test]
+fn baud_rate_divisors() {
+    const SET_BAUDRATE: u8 = 0x03;
+
+    // Expected `(model, port, baud, achieved, value, index)`, as computed by libftdi.
+    let cases = [
+        ("FT232AM", 0, 115_200, 115_385, 0x001A, 0x0000),
+        ("FT232BM", 0, 115_200, 115_385, 0x001A, 0x0000),
+        ("FT232BM", 0, 3_000_000, 3_000_000, 0x0000, 0x0000),
+        ("FT232R", 0, 115_200, 115_385, 0x001A, 0x0000),
+        ("FT232R", 0, 3_000_000, 3_000_000, 0x0000, 0x0000),
+        ("FT232R", 0, 300, 300, 0x2710, 0x0000),
+        ("FT2232C/D", 1, 115_200, 115_385, 0x001A, 0x0002),
+        ("FT2232H", 0, 12_000_000, 12_000_000, 0x0000, 0x0201),
+        ("FT2232H", 1, 12_000_000, 12_000_000, 0x0000, 0x0202),
+        ("FT2232H", 1, 300, 300, 0x2710, 0x0002),
+        ("FT4232H", 3, 115_200, 115_246, 0xC068, 0x0204),
+        ("FT232H", 0, 733, 733, 0xFFF3, 0x0201),
+        ("FT232H", 0, 732, 732, 0x1002, 0x0101),
+    ];
+    for &(model, index, baud, achieved, value, windex) in &cases {
+        let backend = TestBackend::new(model);
+        let controls = backend.controls.clone();
+        let ftdi = Ftdi::from_backend(backend).unwrap();
+        let mut port = ftdi.open_port(index).unwrap();
+
+        controls.lock().unwrap().clear();
+        assert_eq!(
+            port.set_baud_rate(baud).unwrap(),
+            achieved,
+            "{} {}",
+            model,
+            baud
+        );
+        assert_eq!(
+            *controls.lock().unwrap(),
+            [(SET_BAUDRATE, value, windex)],
+            "{} {}",
+            model,
+            baud
+        );
+    }
+}
+
+#[test]
+fn io_traits() {
+    let (ftdi, _) = open("FT-X");
+    let mut port = ftdi.open_port(0).unwrap();
+    port.write_all(b"first line\nsecond").unwrap();
+    port.flush().unwrap();
+
+    let mut reader = BufReader::new(port);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "first line\n");
+}
+
+#[test]
+fn read_timeout() {
+    let (ftdi, _) = open("FT232H");
+    let mut port = ftdi.open_port(0).unwrap();
+    port.set_timeout(Duration::from_millis(10));
+    let err = port.read(&mut [0; 16]).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::Timeout));
+}
+
+#[test]
+fn disconnected() {
+    let (ftdi, sim) = open("FT2232H");
+    let mut port = ftdi.open_port(0).unwrap();
+    sim.unplug();
+
+    let err = port.write_all(b"hi").unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::Disconnected));
+    let err = ftdi.read_eeprom_word(0).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::Disconnected));
+
+    let err = std::io::Read::read(&mut port, &mut [0; 16]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+}
+
+#[test]
+fn eeprom() {
+    let (ftdi, sim) = open("FT232R");
+    ftdi.write_eeprom_word(3, 0x1234).unwrap();
+    assert_eq!(ftdi.read_eeprom_word(3).unwrap(), 0x1234);
+    assert_eq!(sim.eeprom()[3], 0x1234);
+
+    ftdi.erase_eeprom(Duration::from_secs(1)).unwrap();
+    assert_eq!(ftdi.read_eeprom_word(3).unwrap(), 0xFFFF);
+}
+
+#[test]
+fn eeprom_info() {
+    use rftdi::eeprom::EepromType;
+
+    let (ftdi, _) = open("FT232R");
+    assert_eq!(ftdi.eeprom_info().unwrap().ty(), EepromType::Internal);
+    assert_eq!(ftdi.eeprom_info().unwrap().size_words(), 64);
+
+    let (ftdi, _) = open("FT232BM");
+    assert_eq!(ftdi.eeprom_info().unwrap().ty(), EepromType::Absent);
+    assert!(ftdi.read_eeprom_words(0, &mut [0; 4]).is_err());
+    ftdi.write_eeprom_word(0, 0x1234).unwrap();
+    assert_eq!(ftdi.eeprom_info().unwrap().ty(), EepromType::Eeprom93C46);
+
+    let (ftdi, sim) = open("FT2232H");
+    sim.set_eeprom(&[0x0102, 0x0304]);
+    let info = ftdi.eeprom_info().unwrap();
+    assert_eq!(info.ty(), EepromType::Eeprom93C56);
+    assert_eq!(info.size_words(), 128);
+
+    let mut buf = [0; 2];
+    ftdi.read_eeprom_words(0, &mut buf).unwrap();
+    assert_eq!(buf, [0x0102, 0x0304]);
+    ftdi.write_eeprom_words(126, &[0xAAAA, 0x5555]).unwrap();
+    let err = ftdi.read_eeprom_words(127, &mut buf).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::Eeprom));
+    assert!(ftdi.write_eeprom_words(128, &[0]).is_err());
+}
+
+#[test]
+fn eeprom_config() {
+    let (ftdi, sim) = open("FT232R");
+    assert!(ftdi.read_eeprom_config().is_err());
+
+    let mut image = vec![0; 64];
+    // Manufacturer and product strings both point to an empty string descriptor.
+    image[0x07] = 0x0298;
+    image[0x08] = 0x0298;
+    image[0x0C] = 0x0302;
+    rftdi::eeprom::update_checksum("FT232R", &mut image).unwrap();
+    sim.set_eeprom(&image);
+    ftdi.verify_eeprom().unwrap();
+
+    let mut config = ftdi.read_eeprom_config().unwrap();
+    config.product = String::from("Simulated UART");
+    config.serial = Some(String::from("SIM42"));
+    ftdi.write_eeprom_config(&config).unwrap();
+    ftdi.verify_eeprom().unwrap();
+
+    let config = ftdi.read_eeprom_config().unwrap();
+    assert_eq!(config.product, "Simulated UART");
+    assert_eq!(config.serial.as_deref(), Some("SIM42"));
+}
+
+#[test]
+fn eeprom_backup() {
+    use rftdi::eeprom::EepromBackup;
+
+    let (ftdi, sim) = open("FT232R");
+    let mut image = (0..64).collect::<Vec<u16>>();
+    ftdi.write_eeprom_image(&image).unwrap();
+    assert_eq!(ftdi.read_eeprom_image().unwrap(), image);
+    assert!(ftdi.write_eeprom_image(&image[..32]).is_err());
+
+    let mut file = Vec::new();
+    ftdi.backup_eeprom().unwrap().write_to(&mut file).unwrap();
+    let backup = EepromBackup::read_from(&file[..]).unwrap();
+    assert_eq!(backup.model(), "FT232R");
+    assert_eq!(backup.serial(), Some("SIM00001"));
+    assert_eq!(backup.words(), &image[..]);
+
+    image[5] = 0xFFFF;
+    sim.set_eeprom(&image);
+    ftdi.restore_eeprom(&backup).unwrap();
+    assert_eq!(sim.eeprom()[5], 5);
+
+    let (other, _) = open("FT232BM");
+    let err = other.restore_eeprom(&backup).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::Eeprom));
+
+    assert!(EepromBackup::read_from(&b"model: FT232R\n"[..]).is_err());
+    assert!(EepromBackup::read_from(&file[..file.len() - 10]).is_err());
+    let huge = b"rftdi-eeprom-backup 1\nmodel: FT232R\nsize: 1000000000000\n\n";
+    assert!(EepromBackup::read_from(&huge[..]).is_err());
+}
+
+#[test]
+fn eeprom_restore_blank() {
+    use rftdi::eeprom::EepromType;
+
+    let (ftdi, sim) = open("FT2232H");
+    let image = (0..128).map(|word| word * 0x0101).collect::<Vec<u16>>();
+    sim.set_eeprom(&image);
+    let backup = ftdi.backup_eeprom().unwrap();
+
+    ftdi.erase_eeprom(Duration::from_secs(1)).unwrap();
+    assert_eq!(ftdi.eeprom_info().unwrap().ty(), EepromType::Absent);
+    let err = ftdi.write_eeprom_image(&image[..100]).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::Eeprom));
+
+    ftdi.restore_eeprom(&backup).unwrap();
+    assert_eq!(sim.eeprom(), image);
+    let info = ftdi.eeprom_info().unwrap();
+    assert_eq!(info.ty(), EepromType::Eeprom93C56);
+    assert_eq!(info.size_words(), 128);
+
+    // The detected size has to match the image.
+    ftdi.erase_eeprom(Duration::from_secs(1)).unwrap();
+    let err = ftdi.write_eeprom_image(&image[..64]).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::Eeprom));
+}
+
+#[test]
+fn bitbang() {
+    let (ftdi, _) = open("FT232R");
+    let mut port = ftdi.open_port(0).unwrap().into_bitbang(0x0F).unwrap();
+    assert_eq!(port.read_pins().unwrap(), 0xFF);
+
+    // Only the output pins are driven.
+    port.write_pins(0x05).unwrap();
+    assert_eq!(port.read_pins().unwrap(), 0xF5);
+    port.write_pattern(&[0x00, 0x01, 0x02, 0x03, 0xAA]).unwrap();
+    assert_eq!(port.read_pins().unwrap(), 0xFA);
+}
+
+#[test]
+fn sync_bitbang() {
+    let (ftdi, _) = open("FT2232H");
+    let mut port = ftdi.open_port(1).unwrap().into_sync_bitbang(0xF0).unwrap();
+    assert_eq!(port.transfer(&[]).unwrap(), []);
+    assert_eq!(
+        port.transfer(&[0x00, 0x10, 0x2F]).unwrap(),
+        [0xFF, 0x0F, 0x1F]
+    );
+
+    // Data received in serial mode is discarded when switching modes.
+    let mut port = port.into_mode::<rftdi::bitmode::Serial>().unwrap();
+    port.write_all(b"serial data").unwrap();
+    let mut buf = [0; 1];
+    assert_eq!(port.read(&mut buf).unwrap(), 1);
+    port.write_all(b"more").unwrap();
+    let mut port = port.into_sync_bitbang(0xF0).unwrap();
+    assert_eq!(port.transfer(&[0x20]).unwrap(), [0x2F]);
+
+    // Long patterns are split into chunks.
+    let pattern = (0..10_000)
+        .map(|i| ((i % 16) << 4) as u8)
+        .collect::<Vec<_>>();
+    let samples = port.transfer(&pattern).unwrap();
+    assert_eq!(samples.len(), pattern.len());
+    assert_eq!(samples[0], 0x2F);
+    for (sample, byte) in samples[1..].iter().zip(&pattern) {
+        assert_eq!(*sample, byte | 0x0F);
+    }
+}
+
+#[test]
+fn mpsse() {
+    use rftdi::bitmode::Mpsse;
+    use rftdi::mpsse::{BitOrder, Commands, Edge};
+
+    let (ftdi, _) = open("FT2232H");
+    let mut port = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap();
+
+    let mut commands = Commands::new();
+    commands.loopback(true).set_gpio_low(0x00, 0x0B);
+    let low = commands.get_gpio_low();
+    let data = commands.transfer_bytes(Edge::Falling, Edge::Rising, BitOrder::MsbFirst, b"hi");
+    commands.set_gpio_high(0x00, 0xF0);
+    let high = commands.get_gpio_high();
+    let bits = commands.transfer_bits(Edge::Falling, Edge::Rising, BitOrder::MsbFirst, 0x55, 8);
+
+    let responses = port.execute(&commands).unwrap();
+    assert_eq!(responses[low], [0xF4]);
+    assert_eq!(&responses[data], b"hi");
+    assert_eq!(responses[high], [0x0F]);
+    assert_eq!(responses[bits], [0x55]);
+    assert_eq!(responses.as_bytes().len(), 5);
+
+    // Responses larger than the device's buffer are received in multiple batches.
+    let mut commands = Commands::new();
+    let chunks = (0..20)
+        .map(|i| {
+            commands.transfer_bytes(Edge::Falling, Edge::Rising, BitOrder::MsbFirst, &[i; 1000])
+        })
+        .collect::<Vec<_>>();
+    let responses = port.execute(&commands).unwrap();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        assert_eq!(responses[chunk], [i as u8; 1000][..]);
+    }
+
+    // Ports without MPSSE support are rejected.
+    let (ftdi, _) = open("FT232R");
+    let err = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::UnsupportedDevice));
+}
+
+#[test]
+fn mpsse_clock() {
+    use rftdi::bitmode::Mpsse;
+
+    let (ftdi, _) = open("FT2232H");
+    let mut port = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap();
+    assert_eq!(port.set_clock(1_000_000).unwrap(), 1_000_000);
+    assert_eq!(port.set_clock(7_000_000).unwrap(), 6_000_000);
+    assert_eq!(port.set_clock(100_000_000).unwrap(), 30_000_000);
+    // Low frequencies need the divide-by-5 prescaler.
+    assert_eq!(port.set_clock(100).unwrap(), 100);
+    assert_eq!(port.set_clock(1).unwrap(), 91);
+    port.set_three_phase_clocking(true).unwrap();
+    port.set_adaptive_clocking(false).unwrap();
+
+    let (ftdi, _) = open("FT2232C/D");
+    let mut port = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap();
+    assert_eq!(port.set_clock(1_000_000).unwrap(), 1_000_000);
+    assert_eq!(port.set_clock(30_000_000).unwrap(), 6_000_000);
+    let err = port.set_three_phase_clocking(true).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::UnsupportedDevice));
+    let err = port.set_adaptive_clocking(true).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::UnsupportedDevice));
+}
+
+#[test]
+fn spi() {
+    use rftdi::bitmode::Mpsse;
+    use rftdi::spi::{ChipSelect, Mode, Spi};
+
+    let (ftdi, _) = open("FT2232H");
+    let port = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap();
+    let mut spi = Spi::new(port, Mode::Mode3).unwrap();
+    assert_eq!(spi.set_clock(1_000_000).unwrap(), 1_000_000);
+
+    let flash = ChipSelect::active_low(3);
+    let adc = ChipSelect::active_high(12);
+    spi.add_chip_select(flash).unwrap();
+    spi.add_chip_select(adc).unwrap();
+
+    // Without a device attached, the data input reads high.
+    let mut data = [0x12, 0x34];
+    spi.transfer(flash, &mut data).unwrap();
+    assert_eq!(data, [0xFF, 0xFF]);
+    let mut buf = [0; 3];
+    spi.write_read(adc, &[0x01], &mut buf).unwrap();
+    assert_eq!(buf, [0xFF; 3]);
+
+    // Check the pin levels while the chip selects are asserted, with data looped back.
+    let mut batch = spi.batch();
+    batch.commands().loopback(true);
+    batch.select(flash).select(adc);
+    let low = batch.commands().get_gpio_low();
+    let high = batch.commands().get_gpio_high();
+    let echo = batch.transfer(b"spi");
+    batch.deselect(flash);
+    let low_after = batch.commands().get_gpio_low();
+    batch.deselect(adc).commands().loopback(false);
+    let high_after = batch.commands().get_gpio_high();
+
+    let responses = spi.execute(&batch).unwrap();
+    // SCK idles high in mode 3, MOSI is an output, MISO floats high.
+    assert_eq!(responses[low][0] & 0x0F, 0b0101);
+    assert_eq!(responses[low_after][0] & 0x0F, 0b1101);
+    assert_eq!(responses[high][0] & 0x10, 0x10);
+    assert_eq!(responses[high_after][0] & 0x10, 0x00);
+    assert_eq!(&responses[echo], b"spi");
+}
+
+#[test]
+#[should_panic(expected = "not added")]
+fn spi_unknown_chip_select() {
+    use rftdi::bitmode::Mpsse;
+    use rftdi::spi::{ChipSelect, Mode, Spi};
+
+    let (ftdi, _) = open("FT232H");
+    let port = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap();
+    let spi = Spi::new(port, Mode::Mode0).unwrap();
+    spi.batch().select(ChipSelect::active_low(4));
+}
+
+#[test]
+fn i2c() {
+    use rftdi::bitmode::Mpsse;
+    use rftdi::i2c::{Address, I2c, Nack};
+    use std::error::Error as _;
+
+    let nack = |e: rftdi::Error| {
+        assert!(matches!(e.kind(), rftdi::ErrorKind::Nack));
+        *e.source().unwrap().downcast_ref::<Nack>().unwrap()
+    };
+
+    let (ftdi, _) = open("FT232H");
+    let port = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap();
+    let mut i2c = I2c::new(port).unwrap();
+    assert_eq!(i2c.set_clock(400_000).unwrap(), 400_000);
+    assert_eq!(i2c.set_clock(100_000).unwrap(), 100_000);
+    i2c.set_clock_stretching(true).unwrap();
+
+    // Without a device attached, SDA is pulled high and nothing acknowledges.
+    let err = i2c.write(0x50, &[0x00]).unwrap_err();
+    assert_eq!(nack(err), Nack::Address(Address::Seven(0x50)));
+    let err = i2c.read(Address::Ten(0x2A5), &mut [0; 2]).unwrap_err();
+    assert_eq!(nack(err), Nack::Address(Address::Ten(0x2A5)));
+
+    // Simulate a device holding SDA low, which acknowledges everything and reads as 0.
+    let mut batch = i2c.batch();
+    batch.commands().set_gpio_low(0x00, 0x04);
+    batch.write(0x50, &[0x12, 0x34]);
+    let data = batch.write_read(Address::Ten(0x3FF), &[0x56], 3);
+    let empty = batch.read(0x48, 0);
+    let responses = i2c.execute(&batch).unwrap();
+    assert_eq!(responses[data], [0x00; 3]);
+    assert!(responses[empty].is_empty());
+
+    let mut buf = [0xFF; 2];
+    i2c.write_read(0x48, &[0x00], &mut buf).unwrap();
+    assert_eq!(buf, [0x00; 2]);
+
+    // I²C needs 3-phase clocking, which older chips lack.
+    let (ftdi, _) = open("FT2232C/D");
+    let port = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap();
+    let err = I2c::new(port).unwrap_err();
+    assert!(matches!(err.kind(), rftdi::ErrorKind::UnsupportedDevice));
+}
+
+#[test]
+fn i2c_open_drain_emulation() {
+    use rftdi::bitmode::Mpsse;
+    use rftdi::i2c::I2c;
+
+    let (ftdi, _) = open("FT2232H");
+    let port = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap();
+    let mut i2c = I2c::new(port).unwrap();
+
+    // Simulate a device acknowledging everything by holding SDA low.
+    let mut batch = i2c.batch();
+    batch.commands().set_gpio_low(0x00, 0x04);
+    batch.write(0x50, &[0x5A]);
+
+    // Without open-drain outputs, Bytes are clocked out bit by bit, only driving SDA for 0 bits.
+    let driven = batch
+        .commands()
+        .as_bytes()
+        .windows(6)
+        .filter(|w| w[0] == 0x80 && w[3..] == [0x13, 0x00, 0x00])
+        .map(|w| w[2] & 0x02 != 0)
+        .collect::<Vec<_>>();
+    let expected = [0xA0u8, 0x5A]
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| byte & 1 << bit == 0))
+        .collect::<Vec<_>>();
+    assert_eq!(driven, expected);
+    assert!(!batch.commands().as_bytes().contains(&0x11));
+
+    let data = batch.read(0x50, 2);
+    assert_eq!(i2c.execute(&batch).unwrap()[data], [0x00; 2]);
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn i2c_address_out_of_range() {
+    use rftdi::bitmode::Mpsse;
+    use rftdi::i2c::I2c;
+
+    let (ftdi, _) = open("FT2232H");
+    let port = ftdi.open_port(0).unwrap().into_mode::<Mpsse>().unwrap();
+    let i2c = I2c::new(port).unwrap();
+    i2c.batch().write(0x80, &[]);
+}
+
+#[test]
+fn mpsse_bad_command() {
+    use rftdi::bitmode::Mpsse;
+    use rftdi::mpsse::{BadCommand, Commands};
+    use std::error::Error as _;
+
+    let opcode = |e: rftdi::Error| {
+        assert!(matches!(e.kind(), rftdi::ErrorKind::BadCommand));
+        e.source()
+            .unwrap()
+            .downcast_ref::<BadCommand>()
+            .unwrap()
+            .opcode()
+    };
+
+    // Stale data from serial mode is discarded when entering MPSSE mode.
+    let (ftdi, _) = open("FT232H");
+    let mut port = ftdi.open_port(0).unwrap();
+    port.write_all(&[0xFA, 0x00, 0x12]).unwrap();
+    let mut port = port.into_mode::<Mpsse>().unwrap();
+
+    let mut commands = Commands::new();
+    commands.set_gpio_low(0, 0);
+    let pins = commands.get_gpio_low();
+    assert_eq!(port.execute(&commands).unwrap()[pins], [0xFF]);
+
+    let mut commands = Commands::new();
+    commands.raw(&[0xAB], 0);
+    commands.get_gpio_low();
+    assert_eq!(opcode(port.execute(&commands).unwrap_err()), 0xAB);
+
+    // Rejections are reported even if the batch does not read any data.
+    let mut commands = Commands::new();
+    commands.raw(&[0x8F, 0x00, 0x00, 0x00], 0);
+    assert_eq!(opcode(port.execute(&commands).unwrap_err()), 0x00);
+
+    // The engine keeps working afterwards.
+    let mut commands = Commands::new();
+    let pins = commands.get_gpio_high();
+    assert_eq!(port.execute(&commands).unwrap()[pins], [0xFF]);
+}
+
+#[test]
+fn cbus_bitbang() {
+    use rftdi::bitmode::Cbus;
+    use rftdi::ErrorKind;
+
+    // CBUS0 and CBUS1 are in I/O mode, CBUS2 and CBUS3 are not.
+    let (ftdi, sim) = open("FT232R");
+    let mut image = sim.eeprom();
+    image[0x0A] = 0x10AA;
+    sim.set_eeprom(&image);
+
+    let mut port = ftdi.open_port(0).unwrap().into_mode::<Cbus>().unwrap();
+    port.set_pins(0b0011, 0b0001).unwrap();
+    assert_eq!(port.read_cbus().unwrap(), 0b1101);
+    port.set_pins(0b0011, 0b0010).unwrap();
+    assert_eq!(port.read_cbus().unwrap(), 0b1110);
+
+    let err = port.set_pins(0b0100, 0b0100).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Eeprom));
+    let err = port.set_pins(0b0001_0000, 0).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Other));
+    let err = port.set_pins(0b0011, 0b1000_0001).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Other));
+
+    let (ftdi, _) = open("FT2232H");
+    let err = ftdi.open_port(0).unwrap().into_mode::<Cbus>().unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::UnsupportedDevice));
+}
+
+#[test]
+fn ports_on_threads() {
+    let (ftdi, _) = open("FT2232H");
+    let threads = (0..ftdi.num_ports())
+        .map(|index| {
+            let mut port = ftdi.open_port(index).unwrap();
+            std::thread::spawn(move || {
+                let data = vec![index; 1000];
+                port.write_all(&data).unwrap();
+                let mut received = Vec::new();
+                let mut buf = [0; 64];
+                while received.len() < data.len() {
+                    let n = port.read(&mut buf).unwrap();
+                    received.extend_from_slice(&buf[..n]);
+                }
+                assert_eq!(received, data);
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}